@@ -0,0 +1,213 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::Instant;
+
+// ベンチマークの実行設定
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub warmup: usize, // 計測前に結果を捨てて実行する回数
+    pub repeat: usize, // 計測する回数
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { warmup: 1, repeat: 3 }
+    }
+}
+
+// 計測結果の出力形式
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+// 1つのベンチマークの計測結果と統計量（時間の単位はすべて秒）
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub label: String,
+    pub elapsed_nanos: Vec<u128>,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub items: Option<u64>, // 1回の実行で処理する要素数（スループット計算用）
+}
+
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+
+// ソート済みの計測値から最近傍順位法でpパーセンタイルを求める
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl Stats {
+    pub fn from_nanos(label: &str, elapsed_nanos: Vec<u128>, items: Option<u64>) -> Self {
+        assert!(!elapsed_nanos.is_empty(), "at least one sample is required");
+        let mut secs: Vec<f64> = elapsed_nanos.iter().map(|&t| t as f64 / NANOS_PER_SEC).collect();
+        secs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let len = secs.len() as f64;
+        let mean = secs.iter().sum::<f64>() / len;
+        let variance = secs.iter().map(|t| (t - mean) * (t - mean)).sum::<f64>() / len;
+        let median = if secs.len().is_multiple_of(2) {
+            (secs[secs.len() / 2 - 1] + secs[secs.len() / 2]) / 2.0
+        } else {
+            secs[secs.len() / 2]
+        };
+        Self {
+            label: label.to_string(),
+            min: secs[0],
+            max: secs[secs.len() - 1],
+            mean,
+            median,
+            stddev: variance.sqrt(),
+            p90: percentile(&secs, 90.0),
+            p99: percentile(&secs, 99.0),
+            items,
+            elapsed_nanos,
+        }
+    }
+    // 平均実行時間から求めた1秒あたりの処理要素数
+    pub fn throughput(&self) -> Option<f64> {
+        self.items.map(|items| items as f64 / self.mean)
+    }
+}
+
+// fをconfig.warmup回空実行した後config.repeat回実行し、計測結果を返す
+pub fn bench<R>(label: &str, config: &Config, items: Option<u64>, mut f: impl FnMut() -> R) -> Stats {
+    bench_with_setup(label, config, items, || (), |_| f())
+}
+
+// 各実行の前にsetupを呼び出し、その戻り値の参照をfに渡す
+// キャッシュの確保など計測に含めたくない準備処理はsetupで行い、戻り値は計測を終えてから解放する
+pub fn bench_with_setup<S, R>(label: &str, config: &Config, items: Option<u64>,
+                              mut setup: impl FnMut() -> S, mut f: impl FnMut(&S) -> R) -> Stats {
+    for _ in 0..config.warmup {
+        f(&setup());
+    }
+    let mut elapsed_nanos = vec![];
+    for _ in 0..config.repeat.max(1) {
+        let input = setup();
+        let start = Instant::now();
+        let result = f(&input);
+        elapsed_nanos.push(start.elapsed().as_nanos());
+        // 結果と入力（キャッシュなど）の解放にかかる時間は計測に含めない
+        drop(result);
+        drop(input);
+    }
+    Stats::from_nanos(label, elapsed_nanos, items)
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn format_table(results: &[Stats]) -> String {
+    let label_width = results.iter().map(|s| s.label.chars().count()).chain([5]).max().unwrap();
+    let mut out = String::new();
+    writeln!(out, "{:<w$} {:>5} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>15}",
+             "label", "runs", "min [s]", "max [s]", "mean [s]", "median [s]", "stddev [s]",
+             "p90 [s]", "p99 [s]", "throughput [/s]", w = label_width).unwrap();
+    for s in results {
+        let throughput = s.throughput().map_or("-".to_string(), |t| format!("{:.1}", t));
+        writeln!(out, "{:<w$} {:>5} {:>10.4} {:>10.4} {:>10.4} {:>10.4} {:>10.4} {:>10.4} {:>10.4} {:>15}",
+                 s.label, s.elapsed_nanos.len(), s.min, s.max, s.mean, s.median, s.stddev,
+                 s.p90, s.p99, throughput, w = label_width).unwrap();
+    }
+    out
+}
+
+fn format_json(results: &[Stats]) -> String {
+    let entries: Vec<String> = results.iter().map(|s| {
+        let samples: Vec<String> = s.elapsed_nanos.iter().map(|t| t.to_string()).collect();
+        let throughput = s.throughput().map_or("null".to_string(), |t| t.to_string());
+        format!("  {{\"label\": \"{}\", \"runs\": {}, \"min\": {}, \"max\": {}, \"mean\": {}, \
+                 \"median\": {}, \"stddev\": {}, \"p90\": {}, \"p99\": {}, \"throughput\": {}, \
+                 \"elapsed_nanos\": [{}]}}",
+                escape_json(&s.label), s.elapsed_nanos.len(), s.min, s.max, s.mean,
+                s.median, s.stddev, s.p90, s.p99, throughput, samples.join(", "))
+    }).collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+fn format_csv(results: &[Stats]) -> String {
+    let mut out = String::from("label,runs,min,max,mean,median,stddev,p90,p99,throughput\n");
+    for s in results {
+        let throughput = s.throughput().map_or(String::new(), |t| t.to_string());
+        writeln!(out, "{},{},{},{},{},{},{},{},{},{}", escape_csv(&s.label), s.elapsed_nanos.len(),
+                 s.min, s.max, s.mean, s.median, s.stddev, s.p90, s.p99, throughput).unwrap();
+    }
+    out
+}
+
+// 計測結果を指定された形式の文字列に変換する
+pub fn report(results: &[Stats], format: Format) -> String {
+    match format {
+        Format::Table => format_table(results),
+        Format::Json => format_json(results),
+        Format::Csv => format_csv(results),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::benchmark::*;
+
+    fn stats() -> Stats {
+        Stats::from_nanos("a \"b\"", vec![4_000_000_000, 1_000_000_000, 3_000_000_000, 2_000_000_000], Some(10))
+    }
+
+    #[test]
+    fn statistics() {
+        let s = stats();
+        assert_eq!(s.min, 1.0);
+        assert_eq!(s.max, 4.0);
+        assert_eq!(s.mean, 2.5);
+        assert_eq!(s.median, 2.5);
+        assert_eq!(s.p90, 4.0);
+        assert!((s.stddev - 1.25f64.sqrt()).abs() < 1e-12);
+        assert_eq!(s.throughput(), Some(4.0));
+    }
+    #[test]
+    fn machine_readable_output() {
+        let json = report(&[stats()], Format::Json);
+        assert!(json.contains("\"label\": \"a \\\"b\\\"\""));
+        assert!(json.contains("\"elapsed_nanos\": [4000000000, 1000000000, 3000000000, 2000000000]"));
+        let csv = report(&[stats()], Format::Csv);
+        assert_eq!(csv.lines().nth(1).unwrap(), "\"a \"\"b\"\"\",4,1,4,2.5,2.5,1.118033988749895,4,4,4");
+    }
+}
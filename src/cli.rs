@@ -0,0 +1,619 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use rust_grammar_samples::benchmark;
+use rust_grammar_samples::collatz::{self, Cache, CounterCache, MutexCache, NoCache, RwLockCache};
+use rust_grammar_samples::probability_search::{self, Cache as _, Dealer, HashMapCache, Palette, Problem, ShardedCache};
+use rust_grammar_samples::random::XorShift;
+use rust_grammar_samples::{cppenum, threaded_jobs, threads_playground};
+
+pub const USAGE: &str = "\
+Usage: main <SUBCOMMAND> [OPTIONS]
+
+Subcommands:
+  collatz       Collatz sequence search benchmarks
+  probability   Ball stacking probability search benchmarks
+  policy        Export the optimal placement for every reachable state as CSV
+  analyze       Evaluate a partially filled board and print the best tube for each color
+  play          Play the ball stacking game with the solver as an advisor
+  simulate      Estimate success rates of placement strategies by Monte Carlo simulation
+  threads       Thread samples (threads_playground / threaded_jobs)
+  enums         Enum samples (cppenum)
+  simd          SSE intrinsics samples
+  basics        Recursion and closure samples
+  help          Print this message
+
+Common options (collatz / probability):
+  --cache <LIST>        Cache backends separated by commas, or \"all\" [default: all]
+                        collatz: none, mutex, rwlock, counter
+                        probability: none, refcell, mutex, rwlock, atomic, hashmap, sharded,
+                        pooled (sharded cache driven by a fixed-size thread pool),
+                        layered (bottom-up solver evaluating one ball count at a time)
+  --warmup <N>          Warmup runs before measuring [default: 1]
+  --repeat <N>          Measured runs [default: 3]
+  --format <FORMAT>     table, json or csv [default: table]
+
+collatz options:
+  --start <N>           First n of the range [default: 1]
+  --end <N>             Last n of the range [default: 100]
+  --threads <N>         Worker threads [default: 36]
+  --cache-len <N>       Cache length [default: end]
+  --budget <BYTES>      Upper limit of cache memory [default: 1073741824]
+  --verify              Compare every backend against the serial reference
+  --sequence <N>        Print the length and maximum of the sequence from N
+
+probability / policy / analyze / play / simulate options:
+  --width <N>           Number of tubes [default: 2]
+  --height <N>          Capacity of each tube [default: 6]
+  --balls <N>           Number of balls to stack [default: 12]
+  --colors <N>          Number of colors [default: 3]
+  --color-names <LIST>  Color names separated by commas (overrides --colors)
+  --weights <LIST>      Relative probability of each color separated by commas [default: equal]
+  --deck <LIST>         Deal from a bag holding these counts of each color, without replacement
+  --preview <N>         Number of upcoming balls shown before placing the current one [default: 0]
+                        (not supported by policy / analyze / play)
+  --connection <N>      Required number of connected balls [default: 4]
+  --win <EXPR>          Win condition instead of --connection, e.g. \"vertical:4 | horizontal:3\"
+                        vertical:N, horizontal:N, diagonal:N, exact-vertical:N, exact-horizontal:N,
+                        exact-diagonal:N, all-tubes:N, count:COLOR:N combined with &, |, ! and ( )
+  --thread-depth <N>    Recursion depth spawning threads [default: 2]
+  --symmetry            Treat boards differing only by color relabeling as the same state
+
+probability / policy / analyze options:
+  --score <EXPR>        Maximize the expected score instead of the win probability, e.g. \"vertical:0,0,1,3\"
+                        Points for runs of length 1, 2, ... in vertical, horizontal or diagonal
+                        direction (longer runs get the last points), terms summed with +
+  --score-colors <LIST> Factor multiplied to the points of each color separated by commas [default: 1]
+  --adversary           The dealer chooses the worst color instead of a random one
+                        probability reports whether a win is forced, policy exports the forcing strategy
+
+probability options:
+  --exact               Also compute the exact probability as a fraction and print the error of f64
+
+policy options:
+  --output <FILE>       Output file [default: stdout]
+                        The board column uses the same notation as analyze --board
+
+analyze options:
+  --board <BOARD>       Tubes separated by |, each written from the bottom with color initials
+                        (R, G, B, Y, P, O, C, W) or {N} for color N, and . for empty slots,
+                        e.g. \"RGB..|BB....\"
+                        The board determines --width and --height
+  --balls <N>           Number of balls left to stack, including the one in hand
+                        [default: empty slots, or the balls left in --deck if fewer]
+
+play options:
+  --manual              Enter the color of each ball instead of drawing it randomly
+  --seed <N>            Seed of the random dealer [default: current time]
+  --auto                Always place the ball in the best tube without asking
+  --record <FILE>       Save the game record as CSV
+
+simulate options:
+  --strategy <LIST>     Strategies separated by commas, or \"all\" [default: all]
+                        optimal, greedy, random
+  --games <N>           Number of games per strategy [default: 100000]
+  --seed <N>            Seed of the random number generator [default: current time]
+  --threads <N>         Worker threads [default: available parallelism]
+                        Intervals not containing the exact probability are marked with *
+";
+
+// コマンドライン引数を解析した結果
+// オプションは--name valueの形式、値を取らないフラグは--nameの形式で与える
+#[derive(Debug)]
+pub struct Args {
+    pub subcommand: String,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+// 値を取らないオプション
+const FLAGS: [&str; 6] = ["verify", "symmetry", "manual", "auto", "exact", "adversary"];
+
+impl Args {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.peekable();
+        let subcommand = args.next().unwrap_or_else(|| "help".to_string());
+        let mut options = HashMap::new();
+        let mut flags = vec![];
+        while let Some(arg) = args.next() {
+            let name = arg.strip_prefix("--").ok_or(format!("unexpected argument: {}", arg))?;
+            if FLAGS.contains(&name) {
+                flags.push(name.to_string());
+            } else {
+                let value = args.next().ok_or(format!("missing value for --{}", name))?;
+                options.insert(name.to_string(), value);
+            }
+        }
+        Ok(Self { subcommand, options, flags })
+    }
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+    fn get<T>(&self, name: &str, default: T) -> Result<T, String>
+        where T: FromStr, T::Err: Debug
+    {
+        Ok(self.get_opt(name)?.unwrap_or(default))
+    }
+    fn get_opt<T>(&self, name: &str) -> Result<Option<T>, String>
+        where T: FromStr, T::Err: Debug
+    {
+        self.options.get(name)
+            .map(|v| v.parse().map_err(|e| format!("invalid value for --{}: {} ({:?})", name, v, e)))
+            .transpose()
+    }
+    // カンマ区切りのリストを解析する（"all"の場合はallを返す）
+    fn get_list<T: FromStr<Err = String>>(&self, name: &str, all: Vec<T>) -> Result<Vec<T>, String> {
+        match self.options.get(name).map(|s| s.as_str()) {
+            None | Some("all") => Ok(all),
+            Some(s) => s.split(',').map(|v| v.trim().parse()).collect(),
+        }
+    }
+    // カンマ区切りの値のリストを解析する
+    fn get_values<T>(&self, name: &str) -> Result<Option<Vec<T>>, String>
+        where T: FromStr, T::Err: Debug
+    {
+        self.options.get(name)
+            .map(|s| s.split(',')
+                .map(|v| v.trim().parse().map_err(|e| format!("invalid value for --{}: {} ({:?})", name, v, e)))
+                .collect())
+            .transpose()
+    }
+    fn check_options(&self, known: &[&str]) -> Result<(), String> {
+        for name in self.options.keys().chain(&self.flags) {
+            if !known.contains(&name.as_str()) {
+                return Err(format!("unknown option for {}: --{}", self.subcommand, name));
+            }
+        }
+        Ok(())
+    }
+    fn benchmark_config(&self) -> Result<benchmark::Config, String> {
+        let default = benchmark::Config::default();
+        Ok(benchmark::Config {
+            warmup: self.get("warmup", default.warmup)?,
+            repeat: self.get("repeat", default.repeat)?,
+        })
+    }
+}
+
+const BENCHMARK_OPTIONS: [&str; 4] = ["cache", "warmup", "repeat", "format"];
+
+fn bench_collatz<T>(label: &str, config: &benchmark::Config, start: usize, end: usize,
+                    thread_num: usize, mut setup: impl FnMut() -> T) -> benchmark::Stats
+    where T: Cache + Sync + Send + 'static
+{
+    let mut result = None;
+    let stats = benchmark::bench_with_setup(label, config, Some((end + 1 - start) as u64), || Arc::new(setup()),
+        |cache| result = Some(collatz::collatz_len_max_parallel_shared(start, end, thread_num, cache)));
+    let (max_len, max_max) = result.unwrap();
+    eprintln!("{}: max_len = {}, max_value = {}", label, max_len, max_max);
+    stats
+}
+
+fn run_collatz(args: &Args) -> Result<(), String> {
+    args.check_options(&[&BENCHMARK_OPTIONS[..],
+        &["start", "end", "threads", "cache-len", "budget", "verify", "sequence"]].concat())?;
+    let start: usize = args.get("start", 1)?;
+    let end: usize = args.get("end", 100)?;
+    if start == 0 || start > end {
+        return Err(format!("invalid range: {}..={}", start, end));
+    }
+    let thread_num = args.get("threads", 36)?;
+    if thread_num == 0 {
+        return Err("--threads must be at least 1".to_string());
+    }
+    let cache_len = args.get("cache-len", end)?;
+    let cache_budget = args.get("budget", 1 << 30)?;
+    let backends = args.get_list("cache", collatz::Backend::all())?;
+    let config = args.benchmark_config()?;
+    let format = args.get("format", benchmark::Format::Table)?;
+    if let Some(n) = args.get_opt::<u64>("sequence")? {
+        let v = collatz::collatz(n, Vec::<u64>::new());
+        println!("len = {}, max_value = {}", v.len(), v.iter().max().unwrap());
+        return Ok(());
+    }
+    let mut results = vec![];
+    for backend in &backends {
+        results.push(match backend {
+            collatz::Backend::None => bench_collatz("Parallel without cache", &config, start, end, thread_num,
+                || NoCache::with_len(0)),
+            collatz::Backend::Mutex => bench_collatz("Parallel with MutexCache", &config, start, end, thread_num,
+                || MutexCache::with_len_within(cache_len, cache_budget)),
+            collatz::Backend::RwLock => bench_collatz("Parallel with RwLockCache", &config, start, end, thread_num,
+                || RwLockCache::with_len_within(cache_len, cache_budget)),
+            collatz::Backend::Counter => bench_collatz("Parallel with CounterCache", &config, start, end, thread_num,
+                || CounterCache::with_len_within(cache_len, cache_budget)),
+        });
+    }
+    print!("{}", benchmark::report(&results, format));
+    if args.flag("verify") {
+        // キャッシュ・並列実装の結果が直列・キャッシュ無しの基準実装と一致するか検証
+        let mut failed = false;
+        for (name, mismatches) in collatz::verify_all(start, end, thread_num, cache_len, cache_budget) {
+            eprintln!("{}: {} mismatches", name, mismatches.len());
+            for m in mismatches.iter().take(10) {
+                eprintln!("  {}", m);
+            }
+            failed |= !mismatches.is_empty();
+        }
+        if failed {
+            return Err("verification failed".to_string());
+        }
+    }
+    Ok(())
+}
+
+// 密なキャッシュとして確保を許す最大の要素数
+const MAX_DENSE_CACHE_LEN: usize = 1 << 30;
+
+// 確率計算の問題設定に関するオプション
+const PROBLEM_OPTIONS: [&str; 12] = ["width", "height", "balls", "colors", "color-names", "weights", "deck",
+                                      "preview", "connection", "win", "thread-depth", "symmetry"];
+
+// 求める値を変更するオプション（ランダムに配られた結果の勝敗を判定するplay / simulateでは使用できない）
+const OBJECTIVE_OPTIONS: [&str; 3] = ["score", "score-colors", "adversary"];
+
+// 問題の定義に関するオプションを解析する（盤面の大きさと積む個数は含まない）
+fn parse_problem(args: &Args) -> Result<Problem, String> {
+    let default = probability_search::Settings::default();
+    let palette = match args.get_opt::<String>("color-names")? {
+        Some(names) => Palette::with_names(&names.split(',').map(|s| s.trim()).collect::<Vec<_>>()),
+        None => match args.get("colors", default.problem.palette.len())? {
+            0 => return Err("at least one color is required".to_string()),
+            colors => Palette::with_len(colors),
+        },
+    };
+    let mut problem = Problem::new(palette, args.get("connection", 4)?);
+    if let Some(condition) = args.get_opt::<String>("win")? {
+        if args.options.contains_key("connection") {
+            return Err("--win cannot be combined with --connection".to_string());
+        }
+        let condition = probability_search::parse_condition(&condition, &problem.palette)?;
+        if args.flag("symmetry") && !(condition.is_color_symmetric() && condition.is_tube_symmetric()) {
+            return Err("--symmetry requires a condition independent of colors and tube order".to_string());
+        }
+        problem = problem.with_condition(condition);
+    }
+    if let Some(score) = args.get_opt::<String>("score")? {
+        let factors = args.get_values::<f64>("score-colors")?;
+        if let Some(factors) = &factors {
+            if factors.len() != problem.palette.len() {
+                return Err(format!("--score-colors requires {} values, one for each color", problem.palette.len()));
+            }
+            if factors.iter().any(|f| !f.is_finite() || *f < 0.0) {
+                return Err("--score-colors must be non-negative".to_string());
+            }
+        }
+        let score = probability_search::parse_score(&score, factors.as_deref())?;
+        if args.flag("symmetry") && !(score.is_color_symmetric() && score.is_tube_symmetric()) {
+            return Err("--symmetry requires a score independent of colors and tube order".to_string());
+        }
+        problem = problem.with_score(score);
+    } else if args.options.contains_key("score-colors") {
+        return Err("--score-colors requires --score".to_string());
+    }
+    if let Some(weights) = args.get_values::<f64>("weights")? {
+        if weights.len() != problem.palette.len() {
+            return Err(format!("--weights requires {} values, one for each color", problem.palette.len()));
+        }
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) || weights.iter().sum::<f64>() <= 0.0 {
+            return Err("--weights must be non-negative and not all zero".to_string());
+        }
+        problem = problem.with_weights(&weights);
+        if args.flag("symmetry") && !problem.is_uniform() {
+            return Err("--symmetry requires equal weights".to_string());
+        }
+    }
+    if let Some(deck) = args.get_values::<usize>("deck")? {
+        if deck.len() != problem.palette.len() {
+            return Err(format!("--deck requires {} counts, one for each color", problem.palette.len()));
+        }
+        if deck.len() > probability_search::MAX_DECK_COLORS || deck.iter().any(|&c| c > u8::MAX as usize) {
+            return Err(format!("--deck supports at most {} colors and {} balls of each color",
+                               probability_search::MAX_DECK_COLORS, u8::MAX));
+        }
+        if args.options.contains_key("weights") || args.flag("symmetry") {
+            return Err("--deck cannot be combined with --weights or --symmetry".to_string());
+        }
+        problem = problem.with_deck(&deck);
+    }
+    problem = problem.with_adversary(args.flag("adversary"));
+    let preview = args.get("preview", 0)?;
+    if preview > probability_search::MAX_PREVIEW {
+        return Err(format!("at most {} balls can be previewed", probability_search::MAX_PREVIEW));
+    }
+    if preview > 0 && args.flag("symmetry") {
+        return Err("--preview cannot be combined with --symmetry".to_string());
+    }
+    if preview > 0 && problem.palette.len() > probability_search::MAX_PREVIEW_COLORS {
+        return Err(format!("--preview supports at most {} colors", probability_search::MAX_PREVIEW_COLORS));
+    }
+    problem = problem.with_preview(preview);
+    if args.flag("symmetry") && problem.palette.len() > probability_search::MAX_SYMMETRIC_COLORS {
+        return Err(format!("--symmetry supports at most {} colors", probability_search::MAX_SYMMETRIC_COLORS));
+    }
+    Ok(problem.with_symmetry(args.flag("symmetry")))
+}
+
+fn problem_settings(args: &Args) -> Result<probability_search::Settings, String> {
+    let default = probability_search::Settings::default();
+    let settings = probability_search::Settings {
+        width: args.get("width", default.width)?,
+        height: args.get("height", default.height)?,
+        n: args.get("balls", default.n)?,
+        threaded_n: args.get("thread-depth", default.threaded_n)?,
+        problem: parse_problem(args)?,
+    };
+    // 筒の数と高さはBoardを作成できる範囲でなければならない
+    probability_search::Board::new(settings.width, settings.height, settings.problem.palette.len())
+        .map_err(|e| e.to_string())?;
+    if settings.n > settings.width * settings.height {
+        return Err(format!("{} balls do not fit in {} tubes of height {}",
+                           settings.n, settings.width, settings.height));
+    }
+    if let Some(deck) = settings.problem.deck() {
+        if deck.total() < settings.n {
+            return Err(format!("{} balls cannot be dealt from a deck of {}", settings.n, deck.total()));
+        }
+    }
+    Ok(settings)
+}
+
+fn run_probability(args: &Args) -> Result<(), String> {
+    args.check_options(&[&BENCHMARK_OPTIONS[..], &PROBLEM_OPTIONS[..], &OBJECTIVE_OPTIONS[..], &["exact"]].concat())?;
+    let settings = problem_settings(args)?;
+    let mut backends = args.get_list("cache", probability_search::Backend::all())?;
+    // --cacheで明示的に指定した場合は使用できないキャッシュをエラーとし、既定の全種類からは除外するだけとする
+    let explicit = args.options.get("cache").is_some_and(|s| s != "all");
+    // 密なキャッシュは全状態分の配列を確保するため、要素数が大きすぎる場合は使用しない
+    let cache_size = settings.problem.cache_size(settings.width, settings.height);
+    if cache_size.is_none_or(|size| size > MAX_DENSE_CACHE_LEN) {
+        if explicit {
+            if let Some(backend) = backends.iter().find(|b| b.is_dense()) {
+                return Err(format!("board is too large for a dense cache ({:?}); use hashmap or sharded", backend));
+            }
+        } else {
+            eprintln!("note: dense caches are skipped because the board is too large");
+            backends.retain(|b| !b.is_dense());
+        }
+    }
+    // 密なキャッシュは予告の列を区別せず、スレッドプールと層ごとの動的計画法は予告に対応しない
+    if settings.problem.preview() > 0 {
+        if explicit {
+            if let Some(backend) = backends.iter().find(|b| !b.supports_preview()) {
+                return Err(format!("--preview is not supported by {:?}; use hashmap or sharded", backend));
+            }
+        } else {
+            eprintln!("note: dense, pooled and layered backends are skipped because they do not support --preview");
+            backends.retain(|b| b.supports_preview());
+        }
+    }
+    let config = args.benchmark_config()?;
+    let format = args.get("format", benchmark::Format::Table)?;
+    probability_search::calc_probabilities(&settings, &backends, &config, format);
+    if args.flag("exact") {
+        probability_search::verify_exact(&settings);
+    }
+    Ok(())
+}
+
+struct Num {
+    n: isize
+}
+impl Num {
+    fn decr(&mut self) -> &mut Self {
+        self.n -= 1;
+        self
+    }
+}
+fn recursive(n: &mut Num) -> usize {
+    if n.n <= 1 {
+        1
+    } else {
+        recursive(n.decr()) + recursive(n.decr().decr())
+    }
+}
+
+fn run_basics() {
+    let mut n = Num { n: 10 };
+    println!("{}", recursive(&mut n));
+    let add = |x, y| x + y;
+    println!("{}", add(2, 3));
+    let mut n = 1;
+    let mut f = || { n += 1; n };
+    println!("{}", f()); // 2
+    println!("{}", f()); // 3
+    println!("{}", n); // 3
+}
+
+// 方策表に含まれる状態の数（配られない色の行は出力しないため、行数は状態数の色数倍とは限らない）
+fn count_states(entries: &[probability_search::PolicyEntry]) -> usize {
+    entries.iter().map(|e| (e.board, e.n)).collect::<HashSet<_>>().len()
+}
+
+// 到達しうる全状態の方策表をCSV形式で出力する
+fn run_policy(args: &Args) -> Result<(), String> {
+    args.check_options(&[&PROBLEM_OPTIONS[..], &OBJECTIVE_OPTIONS[..], &["output"]].concat())?;
+    let settings = problem_settings(args)?;
+    if settings.problem.preview() > 0 {
+        return Err(format!("--preview is not supported by {}", args.subcommand));
+    }
+    let problem = &settings.problem;
+    let cache = HashMapCache::with_len(0);
+    let board = problem.board(settings.width, settings.height);
+    let entries = if problem.is_adversarial() && problem.score().is_none() {
+        // 配り手が敵対的な場合は必勝を保つ手順だけを書き出す
+        let entries = probability_search::forcing_strategy(settings.n, board, problem, &cache)
+            .ok_or("no forced win exists against an adversarial dealer")?;
+        eprintln!("forced win, states = {}", count_states(&entries));
+        entries
+    } else {
+        let entries = probability_search::policy_table(settings.n, board, problem, &cache);
+        eprintln!("p = {}, states = {}", probability_search::probability(settings.n, board, problem, &cache),
+                  count_states(&entries));
+        entries
+    };
+    let result = match args.get_opt::<String>("output")? {
+        Some(path) => {
+            let file = File::create(&path).map_err(|e| format!("{}: {}", path, e))?;
+            probability_search::write_policy_csv(&entries, &problem.palette, settings.width,
+                                                 &mut BufWriter::new(file))
+        }
+        None => probability_search::write_policy_csv(&entries, &problem.palette, settings.width,
+                                                     &mut BufWriter::new(io::stdout().lock())),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+// 途中まで積んだ盤面から残りを積む場合の確率と、次に配られうる色ごとの最善手を表示する
+fn run_analyze(args: &Args) -> Result<(), String> {
+    // 筒の数と高さは盤面の表記から決まり、計算は直列に行う
+    let options: Vec<&str> = PROBLEM_OPTIONS.iter().copied()
+        .filter(|&name| !["width", "height", "thread-depth"].contains(&name))
+        .collect();
+    args.check_options(&[&options[..], &OBJECTIVE_OPTIONS[..], &["board"]].concat())?;
+    let problem = parse_problem(args)?;
+    let notation = args.get_opt::<String>("board")?.ok_or("--board is required")?;
+    let board = probability_search::Board::parse(&notation, problem.palette.len())
+        .map_err(|e| format!("--board {}: {}", notation, e))?;
+    // 残りの個数を省略した場合は筒が全て埋まるか袋が空になるまで積む
+    let capacity: usize = (0..board.width()).map(|x| board.height() - board.top(x)).sum();
+    let left = problem.deck().and_then(|deck| deck.remaining(&board)).map_or(capacity, |deck| deck.total());
+    let n = args.get("balls", capacity.min(left))?;
+    let analysis = probability_search::analyze_position(n, &board, &problem).map_err(|e| e.to_string())?;
+    let palette = &problem.palette;
+    print!("{}", analysis.board.render(palette));
+    println!("{} balls left, {} = {}", n, if problem.score().is_some() { "expected score" } else { "p" },
+             analysis.probability);
+    for entry in &analysis.entries {
+        let best: Vec<String> = entry.best().iter().map(|x| x.to_string()).collect();
+        let moves: Vec<String> = entry.moves.iter().map(|m| format!("{}: {}", m.x, m.probability)).collect();
+        println!("{:<8} best = {:<6} {}", palette.name(entry.color), best.join(" "), moves.join(", "));
+    }
+    Ok(())
+}
+
+// 盤面と各筒の確率を表示しながら対話形式でゲームを進める
+fn run_play(args: &Args) -> Result<(), String> {
+    args.check_options(&[&PROBLEM_OPTIONS[..], &["manual", "seed", "auto", "record"]].concat())?;
+    let settings = problem_settings(args)?;
+    if settings.problem.preview() > 0 {
+        return Err(format!("--preview is not supported by {}", args.subcommand));
+    }
+    let problem = &settings.problem;
+    let mut dealer = if args.flag("manual") {
+        Dealer::Manual
+    } else {
+        Dealer::Random(args.get_opt("seed")?.map_or_else(XorShift::from_time, XorShift::new))
+    };
+    let cache = HashMapCache::with_len(0);
+    let record = probability_search::play(settings.n, problem.board(settings.width, settings.height), problem,
+                                          &cache, &mut dealer, args.flag("auto"),
+                                          &mut io::stdin().lock(), &mut io::stdout())
+        .map_err(|e| e.to_string())?;
+    if let Some(path) = args.get_opt::<String>("record")? {
+        let file = File::create(&path).map_err(|e| format!("{}: {}", path, e))?;
+        record.write_csv(&problem.palette, &mut BufWriter::new(file)).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+// 各戦略でランダムなゲームを繰り返し、成功率の95%信頼区間を正確な確率と比較する
+fn run_simulate(args: &Args) -> Result<(), String> {
+    args.check_options(&[&PROBLEM_OPTIONS[..], &["strategy", "games", "seed", "threads"]].concat())?;
+    let settings = problem_settings(args)?;
+    let problem = &settings.problem;
+    let strategies = args.get_list("strategy", probability_search::Strategy::all())?;
+    let games = args.get("games", 100_000)?;
+    let seed = args.get_opt("seed")?.unwrap_or_else(|| XorShift::from_time().next_u64());
+    let thread_num = args.get("threads", std::thread::available_parallelism().map_or(1, |n| n.get()))?;
+    let board = problem.board(settings.width, settings.height);
+    // 正確な確率の計算結果はOptimal戦略の最善手の計算にそのまま使う
+    let cache = Arc::new(ShardedCache::with_len(0));
+    let p = probability_search::probability_parallel(settings.n, settings.threaded_n, board, problem, &cache);
+    println!("exact p = {}, seed = {}", p, seed);
+    println!("{:<8} {:>10} {:>10} {:>10} {:>21}", "strategy", "games", "wins", "rate", "95% interval");
+    for strategy in strategies {
+        let estimate = probability_search::simulate_parallel(settings.n, board, problem, strategy, games, seed,
+                                                             thread_num, &cache);
+        let (low, high) = estimate.confidence_interval(1.96);
+        println!("{:<8} {:>10} {:>10} {:>10.6} [{:.6}, {:.6}]{}", strategy, estimate.games, estimate.wins,
+                 estimate.rate(), low, high, if low <= p && p <= high { "" } else { " *" });
+    }
+    Ok(())
+}
+
+// サブコマンドを実行する
+pub fn run(args: &Args) -> Result<(), String> {
+    match args.subcommand.as_str() {
+        "collatz" => run_collatz(args),
+        "probability" => run_probability(args),
+        "policy" => run_policy(args),
+        "analyze" => run_analyze(args),
+        "play" => run_play(args),
+        "simulate" => run_simulate(args),
+        "threads" => {
+            args.check_options(&[])?;
+            threads_playground::threads_playground();
+            threaded_jobs::threaded_jobs();
+            Ok(())
+        }
+        "enums" => {
+            args.check_options(&[])?;
+            cppenum::use_color_type();
+            cppenum::use_color_struct();
+            cppenum::use_color_enum();
+            Ok(())
+        }
+        #[cfg(target_arch = "x86_64")]
+        "simd" => {
+            args.check_options(&[])?;
+            rust_grammar_samples::simd::simdsample();
+            Ok(())
+        }
+        "basics" => {
+            args.check_options(&[])?;
+            run_basics();
+            Ok(())
+        }
+        "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        s => Err(format!("unknown subcommand: {}", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::*;
+
+    fn parse(s: &str) -> Result<Args, String> {
+        Args::parse(s.split_whitespace().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_options() {
+        let args = parse("collatz --end 1000 --verify --cache mutex,rwlock").unwrap();
+        assert_eq!(args.subcommand, "collatz");
+        assert_eq!(args.get("end", 0usize), Ok(1000));
+        assert_eq!(args.get("start", 1usize), Ok(1));
+        assert!(args.flag("verify"));
+        assert_eq!(args.get_list("cache", collatz::Backend::all()),
+                   Ok(vec![collatz::Backend::Mutex, collatz::Backend::RwLock]));
+        assert_eq!(args.get_opt::<u64>("sequence"), Ok(None));
+    }
+    #[test]
+    fn invalid_arguments() {
+        assert!(parse("collatz --end").is_err());
+        assert!(parse("collatz 100").is_err());
+        assert!(parse("collatz --end abc").unwrap().get("end", 0usize).is_err());
+        assert!(parse("collatz --width 3").unwrap().check_options(&["end"]).is_err());
+        assert!(run(&parse("unknown").unwrap()).is_err());
+        assert!(parse_problem(&parse("probability --colors 300 --preview 1").unwrap()).is_err());
+        assert!(parse_problem(&parse("probability --colors 300").unwrap()).is_ok());
+    }
+}
@@ -4,6 +4,9 @@ use std::sync::{Mutex, Arc, RwLock};
 use std::sync::atomic;
 use std::sync::atomic::AtomicUsize;
 use std::marker::{Sync, Send};
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
 
 use crate::indexed_value::IndexedValue;
 
@@ -26,11 +29,57 @@ pub struct CounterCache {
 
 pub struct NoCache { }
 
+// コマンドラインなどから選択するキャッシュの種類
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Backend {
+    None,
+    Mutex,
+    RwLock,
+    Counter,
+}
+
+impl Backend {
+    pub fn all() -> Vec<Self> {
+        vec![Self::None, Self::Mutex, Self::RwLock, Self::Counter]
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "mutex" => Ok(Self::Mutex),
+            "rwlock" => Ok(Self::RwLock),
+            "counter" => Ok(Self::Counter),
+            _ => Err(format!("unknown cache backend: {}", s)),
+        }
+    }
+}
+
 pub trait Cache {
     fn with_len(len: usize) -> Self;
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool { self.len() == 0 }
     fn get(&self, i: usize) -> (usize, u64);
     fn set(&self, i: usize, data: (usize, u64));
+    // 1要素あたりのバイト数（Mutex / RwLockのオーバーヘッドを含む）
+    fn slot_size() -> usize;
+    // 実際に確保しているメモリのバイト数
+    fn footprint(&self) -> usize;
+    // bytesバイトの予算内に収まる最大の長さ
+    fn max_len(bytes: usize) -> usize where Self: Sized {
+        // 要素を持たないキャッシュ（NoCache）は長さ0とする
+        bytes.saturating_sub(mem::size_of::<Self>()).checked_div(Self::slot_size()).unwrap_or(0)
+    }
+    // bytesバイトの予算内に収まる最大の長さでキャッシュを作成する
+    fn with_budget(bytes: usize) -> Self where Self: Sized {
+        Self::with_len(Self::max_len(bytes))
+    }
+    // 長さlenを上限としつつ、bytesバイトの予算を超える場合は長さを切り詰めてキャッシュを作成する
+    fn with_len_within(len: usize, bytes: usize) -> Self where Self: Sized {
+        Self::with_len(cmp::min(len, Self::max_len(bytes)))
+    }
 }
 
 impl Cache for RwLockCache {
@@ -54,6 +103,10 @@ impl Cache for RwLockCache {
             *self.cache[i].write().unwrap() = data;
         }
     }
+    fn slot_size() -> usize { mem::size_of::<RwLock<(usize, u64)>>() }
+    fn footprint(&self) -> usize {
+        mem::size_of::<Self>() + self.cache.capacity() * Self::slot_size()
+    }
 }
 
 impl Cache for MutexCache {
@@ -77,6 +130,10 @@ impl Cache for MutexCache {
             *self.cache[i].lock().unwrap() = data;
         }
     }
+    fn slot_size() -> usize { mem::size_of::<Mutex<(usize, u64)>>() }
+    fn footprint(&self) -> usize {
+        mem::size_of::<Self>() + self.cache.capacity() * Self::slot_size()
+    }
 }
 
 impl Cache for CounterCache {
@@ -105,10 +162,14 @@ impl Cache for CounterCache {
             *self.cache[i].write().unwrap() = data;
         }
     }
+    fn slot_size() -> usize { mem::size_of::<RwLock<(usize, u64)>>() }
+    fn footprint(&self) -> usize {
+        mem::size_of::<Self>() + self.cache.capacity() * Self::slot_size()
+    }
 }
 
 impl CounterCache {
-    fn stats(&self) {
+    pub fn stats(&self) {
         println!("cache try = {}", self.counter.load(atomic::Ordering::Relaxed));
         println!("cache hit = {}", self.hit.load(atomic::Ordering::Relaxed));
     }
@@ -119,6 +180,8 @@ impl Cache for NoCache {
     fn len(&self) -> usize { 0 }
     fn get(&self, _: usize) -> (usize, u64) { (0, 0) }
     fn set(&self, _: usize, _: (usize, u64)) { }
+    fn slot_size() -> usize { 0 }
+    fn footprint(&self) -> usize { 0 }
 }
 
 
@@ -127,7 +190,7 @@ pub fn collatz(n: u64, mut v: Vec<u64>) -> Vec<u64> {
         v.push(n);
         v
     }
-    else if n % 2 == 0 {
+    else if n.is_multiple_of(2) {
         v.push(n / 2);
         collatz(n / 2, v)
     }
@@ -146,7 +209,7 @@ fn collatz_len_max_with_cache(n: u64, cache: &Arc<impl Cache>) -> (usize, u64) {
         if r.0 > 0 {
             return r;
         }
-        let next_n = if n % 2 == 0 { n / 2 } else { 3 * n + 1 };
+        let next_n = if n.is_multiple_of(2) { n / 2 } else { 3 * n + 1 };
         let r = collatz_len_max_with_cache(next_n, cache);
         let result = (r.0 + 1, std::cmp::max(n, r.1));
         cache.set(n as usize, result);
@@ -154,52 +217,169 @@ fn collatz_len_max_with_cache(n: u64, cache: &Arc<impl Cache>) -> (usize, u64) {
     }
 }
 
-fn collatz_len_max(n: u64) -> (usize, u64) {
+// nから始まる系列の長さと最大値をキャッシュを使わずに求める
+pub fn collatz_len_max(n: u64) -> (usize, u64) {
     // thread::sleep(std::time::Duration::from_micros(10));
     if n == 1 {
         (1, 1)
     } else {
-        let next_n = if n % 2 == 0 { n / 2 } else { 3 * n + 1 };
+        let next_n = if n.is_multiple_of(2) { n / 2 } else { 3 * n + 1 };
         let r = collatz_len_max(next_n);
         (r.0 + 1, std::cmp::max(n, r.1))
     }
 }
 
+// start..=endの範囲で最長の系列長と最大値をそれぞれ与えるnを並列に求める
 pub fn collatz_len_max_parallel<T>(start: usize, end: usize, thread_num: usize, cache: T)
+    -> (IndexedValue<usize>, IndexedValue<u64>)
+    where T: Cache + Sync + Send + 'static
+{
+    collatz_len_max_parallel_shared(start, end, thread_num, &Arc::new(cache))
+}
+
+// collatz_len_max_parallelと同じだが、キャッシュを呼び出し元と共有する
+// 計算後もキャッシュは解放されないため、ベンチマークで解放にかかる時間を計測から除くことができる
+pub fn collatz_len_max_parallel_shared<T>(start: usize, end: usize, thread_num: usize, cache: &Arc<T>)
+    -> (IndexedValue<usize>, IndexedValue<u64>)
     where T: Cache + Sync + Send + 'static
 {
+    let init = (IndexedValue::<usize> { n: 0, value: 0 }, IndexedValue::<u64> { n: 0, value: 0 });
+    let fold = |acc: &mut (IndexedValue<usize>, IndexedValue<u64>), n: usize, (len, max): (usize, u64)| {
+        acc.0 = cmp::max(acc.0, IndexedValue::<usize> { n, value: len });
+        acc.1 = cmp::max(acc.1, IndexedValue::<u64> { n, value: max });
+    };
+    run_workers(start, end, thread_num, cache, init, fold).into_iter()
+        .fold(init, |(max_len, max_max), (len, max)| (cmp::max(max_len, len), cmp::max(max_max, max)))
+}
+
+// start..=endのnをthread_num個のスレッドで分担して計算し、各結果をスレッドごとのaccに畳み込む
+fn run_workers<T, A>(start: usize, end: usize, thread_num: usize, cache: &Arc<T>,
+                     init: A, fold: fn(&mut A, usize, (usize, u64))) -> Vec<A>
+    where T: Cache + Sync + Send + 'static, A: Clone + Send + 'static
+{
+    assert!(thread_num > 0, "at least one thread is required");
     let current_num = Arc::new(AtomicUsize::new(start));
-    let cache = Arc::new(cache);
     let mut handles = vec![];
     for _ in 0..thread_num {
         let current_num = Arc::clone(&current_num);
-        let cache = Arc::clone(&cache);
-        let handle = thread::spawn(move || -> (IndexedValue::<usize>, IndexedValue::<u64>) {
-            let mut max_len = IndexedValue::<usize> { n: 0, value: 0 };
-            let mut max_max = IndexedValue::<u64> { n: 0, value: 0 };
+        let cache = Arc::clone(cache);
+        let mut acc = init.clone();
+        let handle = thread::spawn(move || -> A {
             loop {
                 let n = current_num.fetch_add(1, atomic::Ordering::Relaxed);
                 if n > end {
                     break
                 }
-                let (len, max) = if cache.len() > 0 {
+                let r = if !cache.is_empty() {
                     collatz_len_max_with_cache(n as u64, &cache)
                 } else {
                     collatz_len_max(n as u64)
                 };
-                max_len = cmp::max(max_len, IndexedValue::<usize> { n, value: len });
-                max_max = cmp::max(max_max, IndexedValue::<u64> { n, value: max });
+                fold(&mut acc, n, r);
             };
-            (max_len, max_max)
+            acc
         });
         handles.push(handle);
     }
-    let mut max_len = IndexedValue::<usize> { n: 0, value: 0 };
-    let mut max_max = IndexedValue::<u64> { n: 0, value: 0 };
-    for handle in handles {
-        let (max_len_thread, max_max_thread) = handle.join().unwrap();
-        max_len = cmp::max(max_len, max_len_thread);
-        max_max = cmp::max(max_max, max_max_thread);
+    handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+}
+
+// start..=endの各nについて(len, max)を直列・キャッシュ無しで計算する（検証の基準となる実装）
+pub fn collatz_len_max_range(start: usize, end: usize) -> Vec<(usize, u64)> {
+    (start..=end).map(|n| collatz_len_max(n as u64)).collect()
+}
+
+// start..=endの各nについて(len, max)を並列に計算し、nの順に並べて返す
+pub fn collatz_len_max_range_parallel<T>(start: usize, end: usize, thread_num: usize, cache: T)
+    -> Vec<(usize, u64)>
+    where T: Cache + Sync + Send + 'static
+{
+    let fold = |acc: &mut Vec<(usize, (usize, u64))>, n: usize, r: (usize, u64)| acc.push((n, r));
+    let mut results = vec![(0, 0); (end + 1).saturating_sub(start)];
+    for (n, r) in run_workers(start, end, thread_num, &Arc::new(cache), vec![], fold).into_iter().flatten() {
+        results[n - start] = r;
+    }
+    results
+}
+
+// 基準実装と異なる結果となったnの情報
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Mismatch {
+    pub n: usize,
+    pub expected: (usize, u64),
+    pub actual: (usize, u64)
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "n={}: expected (len={}, max={}), actual (len={}, max={})",
+               self.n, self.expected.0, self.expected.1, self.actual.0, self.actual.1)
+    }
+}
+
+fn compare_results(start: usize, expected: &[(usize, u64)], actual: &[(usize, u64)]) -> Vec<Mismatch> {
+    expected.iter().zip(actual).enumerate()
+        .filter(|(_, (e, a))| e != a)
+        .map(|(i, (e, a))| Mismatch { n: start + i, expected: *e, actual: *a })
+        .collect()
+}
+
+// 与えたキャッシュを使った並列計算の結果を直列・キャッシュ無しの基準実装と比較し、不一致のnを返す
+pub fn verify<T>(start: usize, end: usize, thread_num: usize, cache: T) -> Vec<Mismatch>
+    where T: Cache + Sync + Send + 'static
+{
+    let expected = collatz_len_max_range(start, end);
+    let actual = collatz_len_max_range_parallel(start, end, thread_num, cache);
+    compare_results(start, &expected, &actual)
+}
+
+// 全てのキャッシュ実装について基準実装との差分を検証する
+// 基準実装の計算は1回だけ行い、各実装の結果と比較する
+// キャッシュの長さはcache_lenを上限に、cache_budgetバイトに収まるよう切り詰める
+pub fn verify_all(start: usize, end: usize, thread_num: usize, cache_len: usize, cache_budget: usize)
+    -> Vec<(&'static str, Vec<Mismatch>)>
+{
+    let expected = collatz_len_max_range(start, end);
+    vec![
+        ("NoCache", compare_results(start, &expected,
+            &collatz_len_max_range_parallel(start, end, thread_num, NoCache::with_len(0)))),
+        ("MutexCache", compare_results(start, &expected,
+            &collatz_len_max_range_parallel(start, end, thread_num, MutexCache::with_len_within(cache_len, cache_budget)))),
+        ("RwLockCache", compare_results(start, &expected,
+            &collatz_len_max_range_parallel(start, end, thread_num, RwLockCache::with_len_within(cache_len, cache_budget)))),
+        ("CounterCache", compare_results(start, &expected,
+            &collatz_len_max_range_parallel(start, end, thread_num, CounterCache::with_len_within(cache_len, cache_budget)))),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collatz::*;
+
+    #[test]
+    fn reference_values() {
+        let r = collatz_len_max_range(1, 7);
+        assert_eq!(r, vec![(1, 1), (2, 2), (8, 16), (3, 4), (6, 16), (9, 16), (17, 52)]);
+    }
+    #[test]
+    fn all_caches_agree() {
+        for (name, mismatches) in verify_all(1, 10_000, 8, 20_000, usize::MAX) {
+            assert!(mismatches.is_empty(), "{}: {}", name, mismatches[0]);
+        }
+    }
+    #[test]
+    fn budget() {
+        let budget = 1 << 20;
+        let cache = MutexCache::with_budget(budget);
+        assert!(cache.len() > 0);
+        assert!(cache.footprint() <= budget);
+        assert!(cache.footprint() + MutexCache::slot_size() > budget);
+        assert_eq!(RwLockCache::with_len_within(10, budget).len(), 10);
+        assert_eq!(RwLockCache::with_len_within(usize::MAX, budget).len(), RwLockCache::max_len(budget));
+        assert_eq!(NoCache::with_budget(budget).footprint(), 0);
+    }
+    #[test]
+    fn small_cache_agrees() {
+        assert!(verify(1, 10_000, 8, MutexCache::with_len(100)).is_empty());
     }
-    println!("max_len = {}, max_value = {}", max_len, max_max);
 }
\ No newline at end of file
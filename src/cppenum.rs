@@ -1,3 +1,6 @@
+// num_derive 0.3のFromPrimitiveが生成するimplに対する警告を抑制
+#![allow(non_local_definitions)]
+
 // 型チェックによるusizeとの区別が不要な場合はエイリアスが便利
 pub type ColorType = usize;
 pub const RED: ColorType = 0;
@@ -49,6 +52,7 @@ fn print_color_enum(c: ColorEnum) {
     println!("{:?}", c); // Green (cが1のとき)
 }
 
+#[allow(clippy::useless_vec)] // Vecの添字としての使い方を示すためvec!を使う
 pub fn use_color_type() {
     let v = vec![1, 2, 3];
     let ct = RED;
@@ -67,6 +71,7 @@ pub fn use_color_type() {
     1.foo(); // ColorTypeにimplするとusizeにもimplしたことになる
 }
 
+#[allow(clippy::useless_vec)]
 pub fn use_color_struct() {
     let v = vec![1, 2, 3];
     let cs = Color::RED;
@@ -85,6 +90,7 @@ pub fn use_color_struct() {
     println!("{}", cs); // Color::RED
 }
 
+#[allow(clippy::useless_vec)]
 pub fn use_color_enum() {
     let v = vec![1, 2, 3];
     let ce = ColorEnum::Red;
@@ -97,7 +103,7 @@ pub fn use_color_enum() {
     println!("{:?}", ce == ColorEnum::Red); // true
     // for文の範囲指定ではas usizeが必要。（もしくはColorEnumにIteratorを実装）
     for c in ColorEnum::Red as usize..=ColorEnum::Blue as usize {
-        println!("{}", c as usize);
+        println!("{}", c);
     }
     println!("size_of ColorEnum: {}", std::mem::size_of::<ColorEnum>()); // 8 (64 bits)
     println!("size_of ColorEnum::Red: {}", std::mem::size_of_val(&ColorEnum::Red)); // 8 (64 bits)
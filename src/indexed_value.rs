@@ -1,7 +1,7 @@
 use std::cmp;
 use std::fmt;
 
-#[derive(Eq, PartialEq, PartialOrd, Clone, Copy, Debug)]
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
 pub struct IndexedValue<T: Eq + Ord + Clone + Copy> {
     pub n: usize,
     pub value: T
@@ -18,6 +18,12 @@ impl<T: Eq + Ord + Clone + Copy> Ord for IndexedValue<T> {
     }
 }
 
+impl<T: Eq + Ord + Clone + Copy> PartialOrd for IndexedValue<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl<T: fmt::Display + Eq + Ord + Clone + Copy> fmt::Display for IndexedValue<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} (n={})", self.value, self.n)
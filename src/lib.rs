@@ -0,0 +1,15 @@
+// サンプルの各モジュールをライブラリとして公開する
+// main / simdsampleのバイナリはこのライブラリを呼び出すだけのフロントエンド
+#[macro_use]
+extern crate num_derive;
+
+pub mod benchmark;
+pub mod collatz;
+pub mod cppenum;
+pub mod indexed_value;
+pub mod probability_search;
+pub mod random;
+pub mod threaded_jobs;
+pub mod threads_playground;
+#[cfg(target_arch = "x86_64")]
+pub mod simd;
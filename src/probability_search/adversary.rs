@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+
+use super::{evaluate_moves, probability, Board, Cache, PolicyEntry, Problem};
+
+// 配り手が敵対的な問題で、boardから残りn個をどの色の順で配られても条件を満たせる場合はtrueを返す
+pub fn is_forced_win(n: usize, board: Board, problem: &Problem, cache: &impl Cache) -> bool {
+    assert!(problem.is_adversarial(), "a forced win is defined for an adversarial dealer");
+    assert!(problem.score().is_none(), "a forced win is defined for a win condition");
+    probability(n, board, problem, cache) == 1.0
+}
+
+// 必勝の場合に、必勝を保つ手順を返す（必勝でなければNone）
+// 手順に従って積んだ場合に到達しうる全ての状態と配られうる全ての色について、各筒に入れた場合の値を列挙する
+// PolicyEntry::bestが必勝を保つ筒で、手順ではそのうち番号がもっとも小さい筒に入れる
+pub fn forcing_strategy(n: usize, board: Board, problem: &Problem, cache: &impl Cache) -> Option<Vec<PolicyEntry>> {
+    if !is_forced_win(n, board, problem, cache) {
+        return None;
+    }
+    let mut entries = vec![];
+    let mut visited = HashSet::new();
+    let mut stack = vec![(problem.canonical(&board), n)];
+    while let Some((board, n)) = stack.pop() {
+        if n == 0 || !visited.insert(board) {
+            continue;
+        }
+        for color in problem.palette.all().filter(|&c| problem.color_probability(c, &board) > 0.0) {
+            let moves = evaluate_moves(n, board, color, problem, cache);
+            let x = moves.iter().find(|m| m.probability == 1.0).expect("a forced win must be kept").x;
+            let mut next = board;
+            next.drop(x, color);
+            stack.push((problem.canonical(&next), n - 1));
+            entries.push(PolicyEntry { board, n, color, moves });
+        }
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::probability_search::*;
+
+    #[test]
+    fn forced_win() {
+        // 2色を2本の筒に積むなら、色ごとに筒を分ければ3個目で必ず2個揃う
+        let problem = Problem::new(Palette::with_len(2), 2).with_adversary(true);
+        let cache = HashMapCache::with_len(0);
+        assert!(is_forced_win(3, problem.board(2, 3), &problem, &cache));
+        let strategy = forcing_strategy(3, problem.board(2, 3), &problem, &cache).unwrap();
+        assert!(!strategy.is_empty() && strategy.iter().all(|e| !e.best().is_empty()));
+        // 1本では交互に配られると揃わないが、ランダムなら揃う確率は0より大きい
+        assert!(!is_forced_win(3, problem.board(1, 3), &problem, &cache));
+        assert!(forcing_strategy(3, problem.board(1, 3), &problem, &cache).is_none());
+        let random = problem.clone().with_adversary(false);
+        assert_eq!(probability(3, random.board(1, 3), &random, &cache), 0.75);
+        assert_eq!(probability(3, problem.board(1, 3), &problem, &cache), 0.0);
+        // 最悪の場合の確率は直列と並列で一致し、ランダムな場合の確率を超えない
+        let problem = Problem::new(Palette::default(), 3).with_adversary(true);
+        let q: f64 = probability(8, problem.board(3, 4), &problem, &HashMapCache::with_len(0));
+        let r: f64 = probability_parallel(8, 2, problem.board(3, 4), &problem, &Arc::new(ShardedCache::with_len(0)));
+        assert_eq!(q, r);
+        assert!(q <= probability(8, problem.board(3, 4), &problem.clone().with_adversary(false),
+                                 &HashMapCache::with_len(0)));
+    }
+}
@@ -0,0 +1,160 @@
+use std::io::{self, BufRead, Write};
+
+use super::{evaluate_moves, Board, Cache, Color, Move, Palette, Problem, Queue};
+use crate::random::XorShift;
+
+// カラーボールの配り方
+pub enum Dealer {
+    Random(XorShift), // 問題で定義された確率でランダムに配る
+    Manual, // 配られた色を入力してもらう（実物のゲームで使う場合）
+}
+
+// 1手分の記録
+#[derive(Clone, Debug)]
+pub struct Turn {
+    pub color: Color, // 配られた色
+    pub moves: Vec<Move>, // 各筒に入れた場合の確率
+    pub x: usize, // 実際に入れた筒
+}
+
+// 1ゲーム分の記録
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub turns: Vec<Turn>,
+    pub board: Board, // 最終的な盤面
+    pub won: Option<bool>, // 条件を満たしたかどうか（途中で終了した場合はNone）
+}
+
+impl GameRecord {
+    // 記録をCSV形式で書き出す
+    pub fn write_csv(&self, palette: &Palette, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "turn,color,tube,probability,best_probability")?;
+        for (i, turn) in self.turns.iter().enumerate() {
+            let p = turn.moves.iter().find(|m| m.x == turn.x).unwrap().probability;
+            let best = turn.moves.iter().map(|m| m.probability).fold(0.0, f64::max);
+            writeln!(writer, "{},{},{},{},{}", i + 1, palette.name(turn.color), turn.x, p, best)?;
+        }
+        Ok(())
+    }
+}
+
+// 色の入力を解釈する（番号、名前、表示用のラベルのいずれか。大文字小文字は区別しない）
+fn parse_color(s: &str, palette: &Palette) -> Option<Color> {
+    let s = s.trim().to_lowercase();
+    let labels = palette.labels();
+    palette.all().find(|&c| {
+        c.0.to_string() == s || palette.name(c).to_lowercase() == s || labels[c.0].to_lowercase() == s
+    })
+}
+
+// 入力を1行読み込む（入力が終わった場合はNone）
+fn read_line(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(line.trim().to_string()))
+    }
+}
+
+// 対話形式でゲームを進行する
+// 毎手、盤面と各筒に入れた場合の確率を表示し、入れる筒を入力してもらう（空入力で最善手、qで終了）
+// autoがtrueの場合は入力を待たずに最善手を選ぶ
+#[allow(clippy::too_many_arguments)] // 入出力を差し替えてテストできるよう、引数で受け取る
+pub fn play(n: usize, board: Board, problem: &Problem, cache: &impl Cache, dealer: &mut Dealer, auto: bool,
+            input: &mut impl BufRead, output: &mut impl Write) -> io::Result<GameRecord> {
+    let palette = &problem.palette;
+    let labels = palette.labels();
+    let legend: Vec<String> = palette.all().map(|c| format!("{}={}", labels[c.0], palette.name(c))).collect();
+    writeln!(output, "{}", legend.join(" "))?;
+    let mut record = GameRecord { turns: vec![], board, won: None };
+    for rest in (1..=n).rev() {
+        write!(output, "\n{}", record.board.render(palette))?;
+        let color = match dealer {
+            Dealer::Random(rng) => problem.sample_color(&record.board, &Queue::default(), rng),
+            Dealer::Manual => loop {
+                write!(output, "color ({} left)> ", rest)?;
+                output.flush()?;
+                match read_line(input)? {
+                    None => return Ok(record),
+                    Some(s) if s == "q" => return Ok(record),
+                    Some(s) => match parse_color(&s, palette) {
+                        Some(color) if problem.color_probability(color, &record.board) == 0.0 => {
+                            writeln!(output, "{} is never dealt now", palette.name(color))?
+                        }
+                        Some(color) => break color,
+                        None => writeln!(output, "unknown color: {}", s)?,
+                    },
+                }
+            },
+        };
+        let moves = evaluate_moves(rest, record.board, color, problem, cache);
+        let best = moves.iter().map(|m| m.probability).fold(0.0, f64::max);
+        writeln!(output, "ball {} / {}: {}", n - rest + 1, n, palette.name(color))?;
+        for m in &moves {
+            let mark = if best - m.probability <= 1e-12 { " (best)" } else { "" };
+            writeln!(output, "  tube {}: {:.6}{}", m.x, m.probability, mark)?;
+        }
+        let best_x = moves.iter().find(|m| best - m.probability <= 1e-12).unwrap().x;
+        let x = if auto {
+            best_x
+        } else {
+            loop {
+                write!(output, "tube [{}]> ", best_x)?;
+                output.flush()?;
+                match read_line(input)? {
+                    None => return Ok(record),
+                    Some(s) if s == "q" => return Ok(record),
+                    Some(s) if s.is_empty() => break best_x,
+                    Some(s) => match s.parse::<usize>() {
+                        Ok(x) if moves.iter().any(|m| m.x == x) => break x,
+                        _ => writeln!(output, "invalid tube: {}", s)?,
+                    },
+                }
+            }
+        };
+        record.board.drop(x, color);
+        record.turns.push(Turn { color, moves, x });
+    }
+    let won = problem.is_satisfied(&record.board);
+    write!(output, "\n{}", record.board.render(palette))?;
+    writeln!(output, "{}", if won { "success!" } else { "failed" })?;
+    record.won = Some(won);
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probability_search::*;
+    use crate::random::XorShift;
+
+    #[test]
+    fn manual_game() {
+        let problem = Problem::new(Palette::default(), 2);
+        let cache = HashMapCache::with_len(0);
+        // 赤、赤と配られ、1個目は筒1に、2個目は最善手（空入力）に入れる
+        let mut input = "red\n1\nR\n\n".as_bytes();
+        let mut output = vec![];
+        let record = play(2, problem.board(2, 2), &problem, &cache, &mut Dealer::Manual, false,
+                          &mut input, &mut output).unwrap();
+        assert_eq!(record.won, Some(true));
+        assert_eq!(record.turns.iter().map(|t| t.x).collect::<Vec<_>>(), vec![1, 1]);
+        let mut csv = vec![];
+        record.write_csv(&problem.palette, &mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().nth(2), Some("2,Red,1,1,1"));
+    }
+    #[test]
+    fn quit_and_auto() {
+        let problem = Problem::new(Palette::default(), 3);
+        let cache = HashMapCache::with_len(0);
+        let mut output = vec![];
+        let record = play(6, problem.board(2, 3), &problem, &cache, &mut Dealer::Manual, false,
+                          &mut "green\nq\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(record.won, None);
+        assert!(record.turns.is_empty());
+        let record = play(6, problem.board(2, 3), &problem, &cache, &mut Dealer::Random(XorShift::new(7)), true,
+                          &mut "".as_bytes(), &mut output).unwrap();
+        assert_eq!(record.turns.len(), 6);
+        assert!(record.won.is_some());
+    }
+}
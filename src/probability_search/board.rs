@@ -0,0 +1,503 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::color::{bits_for, Color, Palette};
+
+// Boardが持てる筒の最大数
+pub const MAX_WIDTH: usize = 8;
+
+// 色の付け替えによる正規化に対応する最大の色数（付け替えを固定長の配列で持つため）
+pub const MAX_SYMMETRIC_COLORS: usize = 8;
+
+// Boardの操作が失敗した理由
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BoardError {
+    FullTube(usize), // 満杯の筒に積もうとした（筒の番号）
+    InvalidTube(usize), // 存在しない筒を指定した（筒の番号）
+    InvalidColor(Color), // 色のビット表現に収まらない色を積もうとした
+    InvalidDimensions { width: usize, height: usize }, // 筒の数か高さが0、または表現できる範囲を超えている
+    InvalidNotation { position: usize }, // 文字列表記の解釈に失敗した（先頭からの文字数）
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FullTube(x) => write!(f, "tube {} is full", x),
+            Self::InvalidTube(x) => write!(f, "tube {} does not exist", x),
+            Self::InvalidColor(color) => write!(f, "color {} does not fit in the board", color.0),
+            Self::InvalidDimensions { width, height } => {
+                write!(f, "invalid board size: {} tubes of height {}", width, height)
+            }
+            Self::InvalidNotation { position } => write!(f, "invalid board notation at character {}", position),
+        }
+    }
+}
+
+impl std::error::Error for BoardError {}
+
+// width個の筒にカラーボールをheight個積むことができる筒のセットの状態を表現する構造体
+// 1本の筒をu64にビット詰めして固定長の配列で持つため、複製してもヒープ確保が発生しない
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Board {
+    tubes: [u64; MAX_WIDTH], // 筒のビット表現。下から順にbitsビットずつ色のビット表現を詰め、空きは0
+    tops: [u8; MAX_WIDTH], // 筒に積まれているカラーボールの個数
+    width: u8,
+    height: u8,
+    bits: u8, // 1個のカラーボールの色を表現するビット数
+}
+
+impl Board {
+    // colors色のカラーボールを積むBoardを作成する
+    // 筒の数か高さが0、筒の数がMAX_WIDTHを超える、または1本の筒が64ビットに収まらない場合はエラーを返す
+    pub fn new(width: usize, height: usize, colors: usize) -> Result<Self, BoardError> {
+        let bits = bits_for(colors);
+        if width == 0 || width > MAX_WIDTH || height == 0 || height.saturating_mul(bits) > 64 {
+            return Err(BoardError::InvalidDimensions { width, height });
+        }
+        Ok(Self {
+            tubes: [0; MAX_WIDTH],
+            tops: [0; MAX_WIDTH],
+            width: width as u8,
+            height: height as u8,
+            bits: bits as u8,
+        })
+    }
+    // Board::newと同じだが、エラーの場合はパニックする
+    pub fn with_size(width: usize, height: usize, colors: usize) -> Self {
+        Self::new(width, height, colors).unwrap_or_else(|e| panic!("{}", e))
+    }
+    pub fn width(&self) -> usize {
+        self.width as usize
+    }
+    pub fn height(&self) -> usize {
+        self.height as usize
+    }
+    // カラーボールを積める最も高い位置のインデックスを返す
+    pub fn top(&self, x: usize) -> usize {
+        debug_assert!(x < self.width(), "tube {} does not exist", x);
+        self.tops[x] as usize
+    }
+    // x番目の筒の下からy番目に積まれているカラーボールの色を返す
+    pub fn get(&self, x: usize, y: usize) -> Option<Color> {
+        debug_assert!(x < self.width(), "tube {} does not exist", x);
+        if y < self.top(x) {
+            let bit = (self.tubes[x] >> (y * self.bits as usize)) & self.slot_mask();
+            Some(Color(bit as usize - 1))
+        } else {
+            None
+        }
+    }
+    // x番目の筒にカラーボールを落として設置する
+    // 筒が存在しない、満杯である、または色がビット表現に収まらない場合はエラーを返し、Boardは変更しない
+    pub fn try_drop(&mut self, x: usize, color: Color) -> Result<(), BoardError> {
+        if x >= self.width() {
+            return Err(BoardError::InvalidTube(x));
+        }
+        if color.0 >= self.slot_mask() as usize {
+            return Err(BoardError::InvalidColor(color));
+        }
+        let y = self.top(x);
+        if y >= self.height() {
+            return Err(BoardError::FullTube(x));
+        }
+        self.tubes[x] |= (color.to_bit() as u64) << (y * self.bits as usize);
+        self.tops[x] += 1;
+        Ok(())
+    }
+    // try_dropと同じだが、エラーの場合はパニックする
+    pub fn drop(&mut self, x: usize, color: Color) {
+        self.try_drop(x, color).unwrap_or_else(|e| panic!("{}", e))
+    }
+    // 筒をビット表現の値順に並べ替えたBoardを返す
+    // 筒の並びだけが異なるBoardは同じ値になるため、メモ化のキーとして使用できる
+    pub fn canonical(&self) -> Self {
+        let mut board = *self;
+        // 筒の数は高々MAX_WIDTHなので挿入ソートで十分
+        for i in 1..self.width() {
+            let mut j = i;
+            while j > 0 && board.tubes[j - 1] > board.tubes[j] {
+                board.tubes.swap(j - 1, j);
+                board.tops.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+        board
+    }
+    // 色の番号をmapに従って付け替えたBoardを返す（map[元の番号] = 新しい番号）
+    pub fn relabel(&self, map: &[usize]) -> Self {
+        let mut board = *self;
+        let bits = self.bits as usize;
+        for x in 0..self.width() {
+            let mut tube = 0;
+            for y in 0..self.top(x) {
+                let color = self.get(x, y).unwrap();
+                tube |= (Color(map[color.0]).to_bit() as u64) << (y * bits);
+            }
+            board.tubes[x] = tube;
+        }
+        board
+    }
+    // 色の付け替えと筒の並べ替えで互いに移り合うBoardが同じ値になるように正規化したBoardを返す
+    // 色によらない筒の形（筒の中で初めて現れた順に色を付け替えた並び）の順に筒を見て、色が初めて現れた順に
+    // 番号を付け替えてから筒を並べ替える。同じ形の筒が複数ある場合はそれらの見る順を全て試して最小のものを選ぶ
+    // 全ての色の付け替えを試す必要はなく、同じ形の筒が少なければ色数によらず筒の数に比例する時間で求まる
+    pub fn canonical_colors(&self, colors: usize) -> Self {
+        assert!(colors <= MAX_SYMMETRIC_COLORS, "at most {} colors are supported", MAX_SYMMETRIC_COLORS);
+        // 筒の番号を形の順に並べ、同じ形の筒はビット表現の順に並べる（筒の数は高々MAX_WIDTHなので挿入ソートで十分）
+        let width = self.width();
+        let mut shapes = [0; MAX_WIDTH];
+        let mut order = [0; MAX_WIDTH];
+        for x in 0..width {
+            shapes[x] = self.shape(x);
+            order[x] = x;
+            let mut i = x;
+            while i > 0 && (shapes[order[i - 1]], self.tubes[order[i - 1]]) > (shapes[order[i]], self.tubes[order[i]]) {
+                order.swap(i - 1, i);
+                i -= 1;
+            }
+        }
+        let mut best = self.relabel_by_appearance(&order[..width], colors);
+        while self.next_tie_order(&mut order[..width], &shapes) {
+            let board = self.relabel_by_appearance(&order[..width], colors);
+            if board.tubes < best.tubes {
+                best = board;
+            }
+        }
+        best
+    }
+    // orderの順に筒を見て、色が初めて現れた順に番号を付け替えてから筒を並べ替えたBoard
+    fn relabel_by_appearance(&self, order: &[usize], colors: usize) -> Self {
+        let mut map = [MAX_SYMMETRIC_COLORS; MAX_SYMMETRIC_COLORS];
+        let mut next = 0;
+        for &x in order {
+            for y in 0..self.top(x) {
+                let color = self.get(x, y).unwrap();
+                if map[color.0] == MAX_SYMMETRIC_COLORS {
+                    map[color.0] = next;
+                    next += 1;
+                }
+            }
+        }
+        // 積まれていない色の番号は結果に影響しないため、残りの番号を順に割り当てる
+        for m in map[..colors].iter_mut().filter(|m| **m == MAX_SYMMETRIC_COLORS) {
+            *m = next;
+            next += 1;
+        }
+        self.relabel(&map[..colors]).canonical()
+    }
+    // 同じ形の筒の並びごとにビット表現の辞書順で次の並びに進める（最後の並びは最初に戻して前の並びを進める）
+    // 全ての並びを一巡した場合はfalseを返す。ビット表現が同じ筒を入れ替えた並びは現れない
+    fn next_tie_order(&self, order: &mut [usize], shapes: &[u64]) -> bool {
+        let mut end = order.len();
+        while end > 0 {
+            let mut start = end - 1;
+            while start > 0 && shapes[order[start - 1]] == shapes[order[end - 1]] {
+                start -= 1;
+            }
+            let group = &mut order[start..end];
+            match (1..group.len()).rev().find(|&i| self.tubes[group[i - 1]] < self.tubes[group[i]]) {
+                Some(i) => {
+                    let j = (i..group.len()).rev().find(|&j| self.tubes[group[i - 1]] < self.tubes[group[j]]).unwrap();
+                    group.swap(i - 1, j);
+                    group[i..].reverse();
+                    return true;
+                }
+                None => group.reverse(),
+            }
+            end = start;
+        }
+        false
+    }
+    // Boardの状態のビット表現。筒の並びは問わないため筒のビット表現の値順にソートする
+    // width * height * bitsが64を超える場合は上位ビットが失われるため、密なキャッシュの添字には使えない
+    pub fn serialize(&self) -> usize {
+        self.canonical().serialize_in_order()
+    }
+    // 筒を並べ替えずに先頭の筒から順に詰めたBoardのビット表現
+    pub fn serialize_in_order(&self) -> usize {
+        let mut data = 0u128;
+        for &tube in &self.tubes[..self.width()] {
+            data <<= self.bits as usize * self.height();
+            data |= tube as u128;
+        }
+        data as usize
+    }
+    // いずれかの筒において同色のカラーボールがconnection_size個以上連続して積まれていたらtrueを返す
+    pub fn is_connected(&self, connection_size: usize) -> bool {
+        if connection_size <= 1 {
+            return self.tops[..self.width()].iter().any(|&top| top > 0);
+        }
+        let bits = self.bits as usize;
+        for x in 0..self.width() {
+            let top = self.top(x);
+            if top < connection_size {
+                continue;
+            }
+            // 隣接するスロットの排他的論理和を取り、スロット内のいずれかのビットが立っていれば色が異なる
+            let diff = self.tubes[x] ^ (self.tubes[x] >> bits);
+            let mut different = diff;
+            for i in 1..bits {
+                different |= diff >> i;
+            }
+            // 下からi番目とi + 1番目が同色であればi番目のスロットの最下位ビットが立つ
+            let same = !different & self.low_bits(top - 1);
+            // 同色の隣接がconnection_size - 1個連続していればconnection_size個連結している
+            let mut run = same;
+            for i in 1..connection_size - 1 {
+                run &= same >> (i * bits);
+            }
+            if run != 0 {
+                return true;
+            }
+        }
+        false
+    }
+    // 文字列表記からcolors色のカラーボールを積むBoardを作成する
+    // 筒を'|'で区切り、各筒を下から順に色の頭文字（大文字小文字は問わない）で、空きを'.'で表す（例: "RGB..|BB...."）
+    // 頭文字を持たない色は"{8}"のように番号を波括弧で囲んで表す
+    // 筒の高さはもっとも長い筒に合わせ、それより短い筒の上の空きは省略できる
+    pub fn parse(s: &str, colors: usize) -> Result<Self, BoardError> {
+        let tubes = parse_tubes(s)?;
+        let height = tubes.iter().map(|t| t.len()).max().unwrap_or(0);
+        let mut board = Self::new(tubes.len(), height, colors)?;
+        for (x, tube) in tubes.iter().enumerate() {
+            for &color in tube.iter().flatten() {
+                board.try_drop(x, color)?;
+            }
+        }
+        Ok(board)
+    }
+    // 筒を縦に並べたアスキーアートで、paletteの色のラベルを使って盤面を表示する
+    pub fn render(&self, palette: &Palette) -> String {
+        let labels = palette.labels();
+        let cell = labels.iter().map(|s| s.len()).max().unwrap_or(1).max(self.width().to_string().len()) + 2;
+        let mut out = String::new();
+        for y in (0..self.height()).rev() {
+            out.push('|');
+            for x in 0..self.width() {
+                let label = self.get(x, y).map_or("", |c| labels[c.0].as_str());
+                out.push_str(&format!("{:^w$}|", label, w = cell));
+            }
+            out.push('\n');
+        }
+        out.push('+');
+        for _ in 0..self.width() {
+            out.push_str(&format!("{}+", "-".repeat(cell)));
+        }
+        out.push_str("\n ");
+        for x in 0..self.width() {
+            out.push_str(&format!("{:^w$} ", x, w = cell));
+        }
+        out.push('\n');
+        out
+    }
+    // x番目の筒の中で初めて現れた順に色を付け替えたビット表現（色の付け替えによらない筒の形）
+    fn shape(&self, x: usize) -> u64 {
+        let mut map = [MAX_SYMMETRIC_COLORS; MAX_SYMMETRIC_COLORS];
+        let mut next = 0;
+        let mut tube = 0;
+        for y in 0..self.top(x) {
+            let color = self.get(x, y).unwrap();
+            if map[color.0] == MAX_SYMMETRIC_COLORS {
+                map[color.0] = next;
+                next += 1;
+            }
+            tube |= (Color(map[color.0]).to_bit() as u64) << (y * self.bits as usize);
+        }
+        tube
+    }
+    fn slot_mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+    // 下からlen個のスロットの最下位ビットが立ったマスク
+    fn low_bits(&self, len: usize) -> u64 {
+        (0..len).fold(0, |mask, i| mask | 1 << (i * self.bits as usize))
+    }
+}
+
+// 文字列表記を筒ごとのスロットの列（空きはNone）に分解する
+// 空きの上にカラーボールがある場合や解釈できない文字がある場合は、その文字の位置を返す
+fn parse_tubes(s: &str) -> Result<Vec<Vec<Option<Color>>>, BoardError> {
+    let offset = s.chars().count() - s.trim_start().chars().count();
+    let mut chars = s.trim().chars().enumerate().map(|(i, ch)| (i + offset, ch)).peekable();
+    let mut tubes = vec![vec![]];
+    while let Some((position, ch)) = chars.next() {
+        let color = match ch {
+            '|' => {
+                tubes.push(vec![]);
+                continue;
+            }
+            '.' => None,
+            '{' => {
+                let mut digits = String::new();
+                while let Some((_, ch)) = chars.next_if(|&(_, ch)| ch != '}') {
+                    digits.push(ch);
+                }
+                match (chars.next(), digits.parse()) {
+                    (Some(_), Ok(i)) => Some(Color(i)),
+                    _ => return Err(BoardError::InvalidNotation { position }),
+                }
+            }
+            _ => Some(Color::from_letter(ch).ok_or(BoardError::InvalidNotation { position })?),
+        };
+        let tube = tubes.last_mut().unwrap();
+        // 空きの上にカラーボールは積めない
+        if color.is_some() && tube.last() == Some(&None) {
+            return Err(BoardError::InvalidNotation { position });
+        }
+        tube.push(color);
+    }
+    Ok(tubes)
+}
+
+// Board::parseで読み込める文字列表記
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in 0..self.width() {
+            if x > 0 {
+                write!(f, "|")?;
+            }
+            for y in 0..self.height() {
+                match self.get(x, y) {
+                    Some(color) => match color.letter() {
+                        Some(ch) => write!(f, "{}", ch)?,
+                        None => write!(f, "{{{}}}", color.0)?,
+                    },
+                    None => write!(f, ".")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// 色数は既定の色数と、表記中のもっとも大きい番号の色を表現できる数の大きい方とする
+impl FromStr for Board {
+    type Err = BoardError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colors = parse_tubes(s)?.iter()
+            .flatten()
+            .flatten()
+            .try_fold(Palette::default().len(), |colors, &c| {
+                c.0.checked_add(1).map(|n| colors.max(n)).ok_or(BoardError::InvalidColor(c))
+            })?;
+        Self::parse(s, colors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probability_search::*;
+
+    fn board(tubes: &[&[usize]], height: usize, colors: usize) -> Board {
+        let mut board = Board::with_size(tubes.len(), height, colors);
+        for (x, tube) in tubes.iter().enumerate() {
+            for &c in tube.iter() {
+                board.drop(x, Color(c));
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn drop_and_get() {
+        let b = board(&[&[0, 2, 1], &[]], 6, 3);
+        assert_eq!(b.top(0), 3);
+        assert_eq!(b.top(1), 0);
+        assert_eq!(b.get(0, 1), Some(Color::BLUE));
+        assert_eq!(b.get(0, 3), None);
+        assert_eq!(b.get(1, 0), None);
+    }
+    #[test]
+    fn checked_operations() {
+        assert_eq!(Board::new(0, 6, 3), Err(BoardError::InvalidDimensions { width: 0, height: 6 }));
+        assert!(Board::new(MAX_WIDTH + 1, 6, 3).is_err());
+        assert_eq!(Board::new(2, 33, 3), Err(BoardError::InvalidDimensions { width: 2, height: 33 }));
+        let mut b = Board::new(2, 2, 3).unwrap();
+        assert_eq!(b.try_drop(2, Color::RED), Err(BoardError::InvalidTube(2)));
+        assert_eq!(b.try_drop(0, Color(3)), Err(BoardError::InvalidColor(Color(3))));
+        assert_eq!(b.try_drop(0, Color(usize::MAX)), Err(BoardError::InvalidColor(Color(usize::MAX))));
+        assert_eq!(b.try_drop(0, Color::RED), Ok(()));
+        assert_eq!(b.try_drop(0, Color::BLUE), Ok(()));
+        let full = b;
+        assert_eq!(b.try_drop(0, Color::GREEN), Err(BoardError::FullTube(0)));
+        assert_eq!(b, full);
+        assert_eq!(BoardError::FullTube(0).to_string(), "tube 0 is full");
+    }
+    #[test]
+    fn canonical_ignores_tube_order() {
+        let a = board(&[&[0, 2, 1], &[1], &[]], 6, 3);
+        let b = board(&[&[], &[0, 2, 1], &[1]], 6, 3);
+        assert_ne!(a, b);
+        assert_eq!(a.canonical(), b.canonical());
+        assert_eq!(a.serialize(), b.serialize());
+        assert_ne!(a.serialize(), board(&[&[0, 2], &[1, 1], &[]], 6, 3).serialize());
+    }
+    #[test]
+    fn canonical_ignores_color_labels() {
+        let a = board(&[&[0, 0, 1], &[2]], 6, 3);
+        let b = board(&[&[1], &[2, 2, 0]], 6, 3);
+        let c = board(&[&[0, 0, 1], &[0]], 6, 3);
+        assert_eq!(a.canonical_colors(3), b.canonical_colors(3));
+        assert_ne!(a.canonical_colors(3), c.canonical_colors(3));
+        assert_eq!(a.relabel(&[2, 0, 1]), board(&[&[2, 2, 0], &[1]], 6, 3));
+        // 同じ形の筒の並びによらない
+        let f = board(&[&[0, 1], &[1, 2]], 3, 3);
+        let g = board(&[&[1, 2], &[0, 1]], 3, 3);
+        assert_eq!(f.canonical_colors(3), g.canonical_colors(3));
+        for h in [a, c, f, board(&[&[0, 1], &[2, 0], &[1, 2]], 3, 3), board(&[&[0, 1, 2], &[1], &[2], &[]], 3, 3)] {
+            for map in [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]] {
+                assert_eq!(h.relabel(&map).canonical_colors(3), h.canonical_colors(3));
+            }
+        }
+        // 8色でも全ての付け替えを試さずに正規化できる
+        let d = board(&[&[7, 6, 5], &[4, 3], &[2, 1, 0]], 3, 8);
+        let e = board(&[&[0, 1], &[2, 3, 4], &[5, 6, 7]], 3, 8);
+        assert_eq!(d.canonical_colors(8), e.canonical_colors(8));
+        assert_eq!(e.canonical_colors(8), e.canonical());
+    }
+    #[test]
+    fn connection() {
+        let b = board(&[&[0, 1, 1, 1, 0], &[2, 2]], 6, 3);
+        assert!(b.is_connected(1));
+        assert!(b.is_connected(3));
+        assert!(!b.is_connected(4));
+        assert!(board(&[&[], &[1, 1, 1, 1]], 4, 3).is_connected(4));
+        assert!(!board(&[&[0, 0], &[0, 0]], 4, 3).is_connected(3));
+        assert!(!Board::with_size(2, 6, 3).is_connected(1));
+        assert!(board(&[&[4, 4, 4, 4, 4]], 5, 5).is_connected(5));
+    }
+    #[test]
+    fn notation() {
+        let b = board(&[&[0, 1, 2], &[2, 2]], 6, 3);
+        assert_eq!(b.to_string(), "RGB...|BB....");
+        assert_eq!("RGB..|BB....".parse::<Board>(), Ok(b));
+        assert_eq!(" rgb|bb.... ".parse::<Board>(), Ok(b));
+        // 文字列表記を経由しても元のBoardに戻る
+        let boards = [
+            Board::with_size(3, 4, 3),
+            board(&[&[0, 0, 0, 0], &[1], &[]], 4, 3),
+            board(&[&[3, 4, 5, 6, 7], &[7]], 5, 8),
+        ];
+        for b in &boards {
+            assert_eq!(b.to_string().parse::<Board>().as_ref(), Ok(b));
+            assert_eq!(Board::parse(&b.to_string(), 8).unwrap().to_string(), b.to_string());
+        }
+        assert_eq!(Board::parse("RG|Y", 3), Err(BoardError::InvalidColor(Color(3))));
+        assert_eq!("RG|.B".parse::<Board>(), Err(BoardError::InvalidNotation { position: 4 }));
+        assert_eq!("RX|..".parse::<Board>(), Err(BoardError::InvalidNotation { position: 1 }));
+        assert_eq!("".parse::<Board>(), Err(BoardError::InvalidDimensions { width: 1, height: 0 }));
+        // 頭文字を持たない色は番号を波括弧で囲む
+        let b = board(&[&[8, 0], &[12]], 3, 13);
+        assert_eq!(b.to_string(), "{8}R.|{12}..");
+        assert_eq!(b.to_string().parse::<Board>(), Ok(b));
+        assert_eq!("R{1|..".parse::<Board>(), Err(BoardError::InvalidNotation { position: 1 }));
+        assert_eq!("{18446744073709551615}".parse::<Board>(), Err(BoardError::InvalidColor(Color(usize::MAX))));
+        assert_eq!("{99999999999999999999}".parse::<Board>(), Err(BoardError::InvalidNotation { position: 0 }));
+    }
+    #[test]
+    fn render() {
+        let b = board(&[&[0, 2], &[1]], 2, 3);
+        assert_eq!(b.render(&Palette::default()), "| B |   |\n| R | G |\n+---+---+\n  0   1  \n");
+    }
+}
@@ -0,0 +1,225 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use super::Board;
+
+// キャッシュのキー。Boardに問題ごとの名前空間（色の確率など、Boardに現れない問題設定から求める値）、山札の構成、予告されたカラーボールを組み合わせる
+// 名前空間が異なる問題の結果は、同じキャッシュに格納しても混ざらない
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    pub board: Board,
+    pub ordered: bool, // 条件が筒の並びによる場合はtrue（筒を並べ替えずにキーとする）
+    pub namespace: u64,
+    pub deck: u64, // 山札モードで袋に残っているカラーボールの構成（Deck::to_bits。山札モードでなければ0）
+    pub queue: u64, // 予告ありの場合の手元と予告のカラーボール（Queue::to_bits。予告なしなら0）
+}
+
+impl Key {
+    // 筒の並びだけが異なるキーが同じ値になるように正規化する（orderedの場合はそのまま）
+    pub(super) fn canonical(&self) -> Self {
+        if self.ordered {
+            *self
+        } else {
+            Self { board: self.board.canonical(), ..*self }
+        }
+    }
+    // 密なキャッシュの添字
+    // 添字はBoardだけから求めるため、山札や予告を含むキーはNone（格納も参照もしない）とする
+    fn index(&self) -> Option<usize> {
+        if self.deck != 0 || self.queue != 0 {
+            None
+        } else if self.ordered {
+            Some(self.board.serialize_in_order())
+        } else {
+            Some(self.board.serialize())
+        }
+    }
+}
+
+// 確率計算のメモ化に使用するキャッシュのインターフェース
+// Pは格納する確率の型（Probabilityを参照）。全状態分の配列を確保するキャッシュはf64のみに対応する
+pub trait Cache<P = f64> {
+    fn with_len(len: usize) -> Self;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool { self.len() == 0 }
+    fn get(&self, key: &Key) -> Option<P>;
+    fn set(&self, key: &Key, data: P);
+}
+
+// シングルスレッド用のキャッシュ構造体
+// RefCell / Mutex / RwLock / AtomicのキャッシュはBoard::serializeを添字とするため名前空間を区別しない。1つの問題にのみ使用する
+// 山札や予告を含むキーは添字で区別できないため格納せず、キャッシュ無しと同じ計算になる
+// MutexCache / RwLockCacheとインターフェースを共通化するためRefCellで包んでいる
+pub struct RefCellCache {
+    cache: Vec<RefCell<Option<f64>>>,
+}
+
+// Mutexを使用したスレッドセーフなキャッシュ構造体
+pub struct MutexCache {
+    cache: Vec<Mutex<Option<f64>>>,
+}
+
+// RwLockを使用したスレッドセーフなキャッシュ構造体
+pub struct RwLockCache {
+    cache: Vec<RwLock<Option<f64>>>,
+}
+
+// f64のビット表現をAtomicU64に格納するロックフリーのスレッドセーフなキャッシュ構造体
+// 未格納の要素にはUNKNOWN（確率としては現れないNaNのビット表現）を格納しておく
+// 同じ要素に複数のスレッドが同時に書き込む場合も、同じ状態の確率なので値は変わらない
+pub struct AtomicCache {
+    cache: Vec<AtomicU64>,
+}
+
+// AtomicCacheの未格納を示す値
+const UNKNOWN: u64 = u64::MAX;
+
+// 正規化したKeyをキーとするHashMapを使用したシングルスレッド用のキャッシュ構造体
+// 到達した状態の分だけメモリを使用するため、全状態分の配列を確保できない大きなBoardにも使用できる
+pub struct HashMapCache<P = f64> {
+    cache: RefCell<HashMap<Key, P>>,
+}
+
+// HashMapをキーのハッシュ値で複数のシャードに分割し、シャードごとにMutexで保護するスレッドセーフなキャッシュ構造体
+// 異なるシャードへのアクセスは互いにブロックしない
+pub struct ShardedCache<P = f64> {
+    shards: Vec<Mutex<HashMap<Key, P>>>,
+    hasher: RandomState,
+}
+
+// キャッシュを使用しないことを示す空の構造体
+pub struct NoCache { }
+
+impl Cache for RefCellCache {
+    fn with_len(len: usize) -> Self {
+        let mut cache = Vec::<RefCell<Option<f64>>>::with_capacity(len);
+        for _ in 0..len {
+            cache.push(RefCell::new(None));
+        }
+        Self { cache }
+    }
+    fn len(&self) -> usize { self.cache.len() }
+    fn get(&self, key: &Key) -> Option<f64> {
+        *self.cache.get(key.index()?)?.borrow()
+    }
+    fn set(&self, key: &Key, data: f64) {
+        if let Some(slot) = key.index().and_then(|i| self.cache.get(i)) {
+            slot.replace(Some(data));
+        }
+    }
+}
+
+impl Cache for RwLockCache {
+    fn with_len(len: usize) -> Self {
+        let mut cache = Vec::<RwLock<Option<f64>>>::with_capacity(len);
+        for _ in 0..len {
+            cache.push(RwLock::new(None));
+        }
+        Self { cache }
+    }
+    fn len(&self) -> usize { self.cache.len() }
+    fn get(&self, key: &Key) -> Option<f64> {
+        *self.cache.get(key.index()?)?.read().unwrap()
+    }
+    fn set(&self, key: &Key, data: f64) {
+        if let Some(slot) = key.index().and_then(|i| self.cache.get(i)) {
+            *slot.write().unwrap() = Some(data);
+        }
+    }
+}
+
+impl Cache for MutexCache {
+    fn with_len(len: usize) -> Self {
+        let mut cache = Vec::<Mutex<Option<f64>>>::with_capacity(len);
+        for _ in 0..len {
+            cache.push(Mutex::new(None));
+        }
+        Self { cache }
+    }
+    fn len(&self) -> usize { self.cache.len() }
+    fn get(&self, key: &Key) -> Option<f64> {
+        *self.cache.get(key.index()?)?.lock().unwrap()
+    }
+    fn set(&self, key: &Key, data: f64) {
+        if let Some(slot) = key.index().and_then(|i| self.cache.get(i)) {
+            *slot.lock().unwrap() = Some(data);
+        }
+    }
+}
+
+impl Cache for AtomicCache {
+    fn with_len(len: usize) -> Self {
+        Self { cache: (0..len).map(|_| AtomicU64::new(UNKNOWN)).collect() }
+    }
+    fn len(&self) -> usize { self.cache.len() }
+    fn get(&self, key: &Key) -> Option<f64> {
+        let bits = self.cache.get(key.index()?)?.load(Ordering::Relaxed);
+        if bits == UNKNOWN {
+            None
+        } else {
+            Some(f64::from_bits(bits))
+        }
+    }
+    fn set(&self, key: &Key, data: f64) {
+        if let Some(slot) = key.index().and_then(|i| self.cache.get(i)) {
+            slot.store(data.to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+impl<P: Clone> Cache<P> for HashMapCache<P> {
+    // lenは初期容量として使用する
+    fn with_len(len: usize) -> Self {
+        Self { cache: RefCell::new(HashMap::with_capacity(len)) }
+    }
+    // 格納済みの要素数を返す
+    fn len(&self) -> usize { self.cache.borrow().len() }
+    fn get(&self, key: &Key) -> Option<P> {
+        self.cache.borrow().get(&key.canonical()).cloned()
+    }
+    fn set(&self, key: &Key, data: P) {
+        self.cache.borrow_mut().insert(key.canonical(), data);
+    }
+}
+
+// ShardedCacheのシャード数（ロックの競合を減らすためスレッド数より十分多くする）
+const SHARD_NUM: usize = 64;
+
+impl<P> ShardedCache<P> {
+    fn shard(&self, key: &Key) -> &Mutex<HashMap<Key, P>> {
+        &self.shards[self.hasher.hash_one(key) as usize % self.shards.len()]
+    }
+}
+
+impl<P: Clone> Cache<P> for ShardedCache<P> {
+    // lenは全シャード合計の初期容量として使用する
+    fn with_len(len: usize) -> Self {
+        let shards = (0..SHARD_NUM)
+            .map(|_| Mutex::new(HashMap::with_capacity(len / SHARD_NUM)))
+            .collect();
+        Self { shards, hasher: RandomState::new() }
+    }
+    // 格納済みの要素数を返す
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+    fn get(&self, key: &Key) -> Option<P> {
+        let key = key.canonical();
+        self.shard(&key).lock().unwrap().get(&key).cloned()
+    }
+    fn set(&self, key: &Key, data: P) {
+        let key = key.canonical();
+        self.shard(&key).lock().unwrap().insert(key, data);
+    }
+}
+
+impl Cache for NoCache {
+    fn with_len(_: usize) -> Self { Self { } }
+    fn len(&self) -> usize { 0 }
+    fn get(&self, _: &Key) -> Option<f64> { None }
+    fn set(&self, _: &Key, _: f64) { }
+}
+
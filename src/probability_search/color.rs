@@ -0,0 +1,92 @@
+// カラーボールの色。色の種類数や名前はPaletteで定義し、ここでは0から始まる番号だけを持つ
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Color(pub usize);
+
+impl Color {
+    pub const RED: Self = Self(0);
+    pub const GREEN: Self = Self(1);
+    pub const BLUE: Self = Self(2);
+    // 色のビット表現（Noneを0としたいので番号0の色を1とする）
+    pub(super) fn to_bit(self) -> usize {
+        self.0 + 1
+    }
+    // Boardの文字列表記に使う1文字（既定の色の名前の頭文字。既定の名前がない番号の色はNone）
+    pub fn letter(self) -> Option<char> {
+        DEFAULT_COLOR_NAMES.get(self.0).and_then(|name| name.chars().next())
+    }
+    // 文字列表記の1文字から色を求める（大文字小文字は区別しない）
+    pub fn from_letter(ch: char) -> Option<Self> {
+        (0..DEFAULT_COLOR_NAMES.len()).map(Self).find(|c| c.letter() == Some(ch.to_ascii_uppercase()))
+    }
+}
+
+// Paletteで名前を指定しなかった場合に使用する色の名前
+const DEFAULT_COLOR_NAMES: [&str; 8] = ["Red", "Green", "Blue", "Yellow", "Purple", "Orange", "Cyan", "White"];
+
+// 問題で使用する色の種類と名前の定義
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Palette {
+    names: Vec<String>,
+}
+
+impl Palette {
+    // len色のパレットを作成する（名前は既定の名前を先頭から順に割り当てる）
+    pub fn with_len(len: usize) -> Self {
+        assert!(len > 0, "palette must have at least one color");
+        let names = (0..len)
+            .map(|i| DEFAULT_COLOR_NAMES.get(i).map_or(format!("Color{}", i), |s| s.to_string()))
+            .collect();
+        Self { names }
+    }
+    pub fn with_names(names: &[&str]) -> Self {
+        assert!(!names.is_empty(), "palette must have at least one color");
+        Self { names: names.iter().map(|s| s.to_string()).collect() }
+    }
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+    pub fn all(&self) -> impl Iterator<Item = Color> {
+        (0..self.len()).map(Color)
+    }
+    pub fn name(&self, color: Color) -> &str {
+        &self.names[color.0]
+    }
+    // 盤面の表示に使う色のラベル。頭文字が重複する場合は色の番号を使う
+    pub fn labels(&self) -> Vec<String> {
+        let initials: Vec<String> = self.all()
+            .map(|c| self.name(c).chars().next().map_or(String::new(), |ch| ch.to_uppercase().collect()))
+            .collect();
+        let unique = initials.iter().enumerate()
+            .all(|(i, s)| !s.is_empty() && !initials[..i].contains(s));
+        if unique {
+            initials
+        } else {
+            self.all().map(|c| c.0.to_string()).collect()
+        }
+    }
+    // 全色とNoneを表現できるビット数（1色なら1ビット、2～3色なら2ビット、4～7色なら3ビット）
+    pub fn bits(&self) -> usize {
+        bits_for(self.len())
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::with_len(3)
+    }
+}
+
+// colors色とNoneを表現できるビット数
+pub(super) fn bits_for(colors: usize) -> usize {
+    let mut n = colors;
+    let mut count = 1;
+    while n > 1 {
+        count += 1;
+        n >>= 1;
+    }
+    count
+}
+
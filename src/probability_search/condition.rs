@@ -0,0 +1,332 @@
+use std::fmt::Debug;
+
+use super::{Board, Color, Palette};
+
+// 積み終わったBoardが条件を満たしているかを判定するインターフェース
+// Problemに持たせてスレッド間で共有するため、Send + Syncを要求する
+pub trait WinCondition: Debug + Send + Sync {
+    fn is_satisfied(&self, board: &Board) -> bool;
+    // 筒を並べ替えても判定が変わらない場合はtrueを返す（メモ化のキーで筒の並びを区別しなくてよい）
+    fn is_tube_symmetric(&self) -> bool { true }
+    // 色を付け替えても判定が変わらない場合はtrueを返す（Problem::symmetryを使用できる）
+    fn is_color_symmetric(&self) -> bool { true }
+}
+
+// 同色のカラーボールが並ぶ向き
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Vertical, // 1本の筒の中で縦に並ぶ
+    Horizontal, // 隣り合う筒の同じ高さに並ぶ
+    Diagonal, // 隣り合う筒で1段ずつ高さがずれて並ぶ（右上がり、右下がりの両方）
+}
+
+impl Direction {
+    // 1つ先のマスへの移動量の一覧
+    pub(super) fn steps(&self) -> &'static [(isize, isize)] {
+        match self {
+            Self::Vertical => &[(0, 1)],
+            Self::Horizontal => &[(1, 0)],
+            Self::Diagonal => &[(1, 1), (1, -1)],
+        }
+    }
+}
+
+// 同色のカラーボールがdirectionの向きにlength個以上（exactの場合はちょうどlength個）連続して並んでいれば満たす
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Run {
+    pub direction: Direction,
+    pub length: usize,
+    pub exact: bool, // trueの場合、length個より長く連続している並びは数えない
+}
+
+impl Run {
+    pub fn vertical(length: usize) -> Self {
+        Self { direction: Direction::Vertical, length, exact: false }
+    }
+    pub fn horizontal(length: usize) -> Self {
+        Self { direction: Direction::Horizontal, length, exact: false }
+    }
+    pub fn diagonal(length: usize) -> Self {
+        Self { direction: Direction::Diagonal, length, exact: false }
+    }
+    // ちょうどlength個の並びだけを数える
+    pub fn exact(self) -> Self {
+        Self { exact: true, ..self }
+    }
+    // (x, y)から(dx, dy)の向きに並ぶ、これ以上伸ばせない同色の並びの色と長さを列挙する
+    pub(super) fn runs<'a>(board: &'a Board, (dx, dy): (isize, isize)) -> impl Iterator<Item = (Color, usize)> + 'a {
+        let get = move |x: isize, y: isize| {
+            if x < 0 || y < 0 || x as usize >= board.width() {
+                None
+            } else {
+                board.get(x as usize, y as usize)
+            }
+        };
+        (0..board.width() as isize)
+            .flat_map(move |x| (0..board.top(x as usize) as isize).map(move |y| (x, y)))
+            // 1つ手前のマスが同色でなければ並びの始点
+            .filter(move |&(x, y)| get(x - dx, y - dy) != get(x, y))
+            .map(move |(x, y)| {
+                let color = get(x, y);
+                (color.unwrap(), (1..).take_while(|&i| get(x + i * dx, y + i * dy) == color).count() + 1)
+            })
+    }
+}
+
+impl WinCondition for Run {
+    fn is_satisfied(&self, board: &Board) -> bool {
+        // 長さ0の並びは向きによらず常に満たす（空のBoardでも満たす）
+        if self.length == 0 {
+            return true;
+        }
+        if self.direction == Direction::Vertical && !self.exact {
+            // もっとも多く使う条件なのでビット演算による判定を使う
+            return board.is_connected(self.length);
+        }
+        self.direction.steps().iter().any(|&step| {
+            Self::runs(board, step).any(|(_, len)| if self.exact { len == self.length } else { len >= self.length })
+        })
+    }
+    fn is_tube_symmetric(&self) -> bool {
+        self.direction == Direction::Vertical
+    }
+}
+
+// 全ての筒にそれぞれ同色のカラーボールがlength個以上連続して積まれていれば満たす
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AllTubes {
+    pub length: usize,
+}
+
+impl WinCondition for AllTubes {
+    fn is_satisfied(&self, board: &Board) -> bool {
+        (0..board.width()).all(|x| {
+            // 下から順に同色の連続数を数え、length個に達したら満たす
+            let mut run = 0;
+            (0..board.top(x)).any(|y| {
+                run = if y > 0 && board.get(x, y) == board.get(x, y - 1) { run + 1 } else { 1 };
+                run >= self.length
+            }) || self.length == 0
+        })
+    }
+}
+
+// colorのカラーボールが全ての筒の合計でcount個以上積まれていれば満たす
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ColorCount {
+    pub color: Color,
+    pub count: usize,
+}
+
+impl WinCondition for ColorCount {
+    fn is_satisfied(&self, board: &Board) -> bool {
+        let count = (0..board.width())
+            .flat_map(|x| (0..board.top(x)).map(move |y| (x, y)))
+            .filter(|&(x, y)| board.get(x, y) == Some(self.color))
+            .count();
+        count >= self.count
+    }
+    fn is_color_symmetric(&self) -> bool {
+        false
+    }
+}
+
+// 全ての条件を満たせば満たす
+#[derive(Debug)]
+pub struct And(pub Vec<Box<dyn WinCondition>>);
+
+// いずれかの条件を満たせば満たす
+#[derive(Debug)]
+pub struct Or(pub Vec<Box<dyn WinCondition>>);
+
+// 条件を満たさなければ満たす
+#[derive(Debug)]
+pub struct Not(pub Box<dyn WinCondition>);
+
+impl WinCondition for And {
+    fn is_satisfied(&self, board: &Board) -> bool {
+        self.0.iter().all(|c| c.is_satisfied(board))
+    }
+    fn is_tube_symmetric(&self) -> bool {
+        self.0.iter().all(|c| c.is_tube_symmetric())
+    }
+    fn is_color_symmetric(&self) -> bool {
+        self.0.iter().all(|c| c.is_color_symmetric())
+    }
+}
+
+impl WinCondition for Or {
+    fn is_satisfied(&self, board: &Board) -> bool {
+        self.0.iter().any(|c| c.is_satisfied(board))
+    }
+    fn is_tube_symmetric(&self) -> bool {
+        self.0.iter().all(|c| c.is_tube_symmetric())
+    }
+    fn is_color_symmetric(&self) -> bool {
+        self.0.iter().all(|c| c.is_color_symmetric())
+    }
+}
+
+impl WinCondition for Not {
+    fn is_satisfied(&self, board: &Board) -> bool {
+        !self.0.is_satisfied(board)
+    }
+    fn is_tube_symmetric(&self) -> bool {
+        self.0.is_tube_symmetric()
+    }
+    fn is_color_symmetric(&self) -> bool {
+        self.0.is_color_symmetric()
+    }
+}
+
+// 条件式の文字列を解析する
+// 条件は"vertical:4"、"horizontal:3"、"diagonal:3"、"exact-vertical:2"（exact-horizontal、exact-diagonalも同様）、
+// "all-tubes:2"、"count:Red:5"（色は名前か番号）のいずれかで、'&'（かつ）、'|'（または）、'!'（否定）、括弧で組み合わせる
+pub fn parse_condition(s: &str, palette: &Palette) -> Result<Box<dyn WinCondition>, String> {
+    let mut parser = Parser { tokens: tokenize(s), pos: 0, palette };
+    let condition = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(condition),
+        Some(token) => Err(format!("unexpected '{}' in condition", token)),
+    }
+}
+
+// 記号と、それ以外の文字の並びに分割する
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    for ch in s.chars() {
+        if "&|!()".contains(ch) || ch.is_whitespace() {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            if !ch.is_whitespace() {
+                tokens.push(ch.to_string());
+            }
+        } else {
+            word.push(ch);
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+// 再帰下降構文解析器（優先順位は'!'、'&'、'|'の順）
+struct Parser<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    palette: &'a Palette,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+    fn accept(&mut self, symbol: &str) -> bool {
+        let found = self.tokens.get(self.pos).is_some_and(|t| t == symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn or(&mut self) -> Result<Box<dyn WinCondition>, String> {
+        let mut conditions = vec![self.and()?];
+        while self.accept("|") {
+            conditions.push(self.and()?);
+        }
+        Ok(if conditions.len() == 1 { conditions.pop().unwrap() } else { Box::new(Or(conditions)) })
+    }
+    fn and(&mut self) -> Result<Box<dyn WinCondition>, String> {
+        let mut conditions = vec![self.factor()?];
+        while self.accept("&") {
+            conditions.push(self.factor()?);
+        }
+        Ok(if conditions.len() == 1 { conditions.pop().unwrap() } else { Box::new(And(conditions)) })
+    }
+    fn factor(&mut self) -> Result<Box<dyn WinCondition>, String> {
+        if self.accept("!") {
+            return Ok(Box::new(Not(self.factor()?)));
+        }
+        if self.accept("(") {
+            let condition = self.or()?;
+            return if self.accept(")") { Ok(condition) } else { Err("missing ')' in condition".to_string()) };
+        }
+        let palette = self.palette;
+        let token = self.next().ok_or("unexpected end of condition")?;
+        let fields: Vec<&str> = token.split(':').collect();
+        let number = |s: &str| s.parse::<usize>().map_err(|e| format!("invalid number in {}: {}", token, e));
+        let condition: Box<dyn WinCondition> = match fields[..] {
+            ["vertical", n] => Box::new(Run::vertical(number(n)?)),
+            ["horizontal", n] => Box::new(Run::horizontal(number(n)?)),
+            ["diagonal", n] => Box::new(Run::diagonal(number(n)?)),
+            ["exact-vertical", n] => Box::new(Run::vertical(number(n)?).exact()),
+            ["exact-horizontal", n] => Box::new(Run::horizontal(number(n)?).exact()),
+            ["exact-diagonal", n] => Box::new(Run::diagonal(number(n)?).exact()),
+            ["all-tubes", n] => Box::new(AllTubes { length: number(n)? }),
+            ["count", color, n] => {
+                let color = palette.all()
+                    .find(|&c| c.0.to_string() == color || palette.name(c).eq_ignore_ascii_case(color))
+                    .ok_or(format!("unknown color in {}", token))?;
+                Box::new(ColorCount { color, count: number(n)? })
+            }
+            _ => return Err(format!("unknown condition: {}", token)),
+        };
+        Ok(condition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probability_search::*;
+
+    fn board(tubes: &[&[usize]], height: usize) -> Board {
+        let mut board = Board::with_size(tubes.len(), height, 3);
+        for (x, tube) in tubes.iter().enumerate() {
+            for &c in tube.iter() {
+                board.drop(x, Color(c));
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn runs() {
+        // 0 1 0
+        // 1 0 1
+        // 0 0 1
+        let b = board(&[&[0, 1, 0], &[0, 0, 1], &[1, 1, 0]], 3);
+        assert!(Run::horizontal(2).is_satisfied(&b));
+        assert!(!Run::horizontal(3).is_satisfied(&b));
+        assert!(Run::diagonal(3).is_satisfied(&b));
+        assert!(!Run::diagonal(3).is_satisfied(&board(&[&[0, 1, 0], &[0, 0, 1], &[1, 2, 2]], 3)));
+        assert!(Run::vertical(2).exact().is_satisfied(&b));
+        assert!(!Run::vertical(3).is_satisfied(&b));
+        assert!(!Run::vertical(1).exact().is_satisfied(&board(&[&[0, 0]], 3)));
+        assert!(!Run::horizontal(2).is_tube_symmetric() && Run::vertical(2).is_tube_symmetric());
+        // 長さ0の並びはどの向きでも空のBoardで満たす
+        let empty = board(&[&[], &[]], 3);
+        for run in [Run::vertical(0), Run::horizontal(0), Run::diagonal(0), Run::vertical(0).exact()] {
+            assert!(run.is_satisfied(&empty), "{:?}", run);
+        }
+    }
+    #[test]
+    fn combinations() {
+        let b = board(&[&[0, 0], &[1, 1, 2]], 3);
+        assert!(AllTubes { length: 2 }.is_satisfied(&b));
+        assert!(!AllTubes { length: 2 }.is_satisfied(&board(&[&[0, 0], &[1, 2]], 3)));
+        assert!(ColorCount { color: Color::RED, count: 2 }.is_satisfied(&b));
+        assert!(!ColorCount { color: Color::BLUE, count: 2 }.is_satisfied(&b));
+        let palette = Palette::default();
+        let parse = |s| parse_condition(s, &palette).unwrap();
+        assert!(parse("count:Blue:1 & !(horizontal:2 | vertical:3)").is_satisfied(&b));
+        assert!(!parse("count:2:2 | all-tubes:3").is_satisfied(&b));
+        assert!(!parse("vertical:2 & count:red:2").is_color_symmetric());
+        assert!(parse_condition("vertical:2 &", &palette).is_err());
+        assert!(parse_condition("(vertical:2", &palette).is_err());
+        assert!(parse_condition("count:Black:1", &palette).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use super::{Board, Color};
+
+// 山札モードで扱える最大の色数（1色あたり8ビットでu64に詰めるため）
+pub const MAX_DECK_COLORS: usize = 8;
+
+// 袋に入っているカラーボールの色ごとの個数
+// 1色あたり高々255個まで数え、色ごとの個数を1バイトずつ並べたものがそのままキャッシュのキー（to_bits）になる
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Deck {
+    counts: [u8; MAX_DECK_COLORS],
+}
+
+impl Deck {
+    // countsの色番号順の個数から作成する
+    pub fn new(counts: &[usize]) -> Self {
+        assert!(counts.len() <= MAX_DECK_COLORS, "a deck supports at most {} colors", MAX_DECK_COLORS);
+        let mut deck = Self { counts: [0; MAX_DECK_COLORS] };
+        for (i, &count) in counts.iter().enumerate() {
+            deck.counts[i] = u8::try_from(count).expect("at most 255 balls of each color are supported");
+        }
+        deck
+    }
+    pub fn count(&self, color: Color) -> usize {
+        self.counts[color.0] as usize
+    }
+    // 袋に残っている総数
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|&c| c as usize).sum()
+    }
+    // Boardに積まれているカラーボールを取り除いた残りを返す（足りない色があればNone）
+    pub fn remaining(&self, board: &Board) -> Option<Self> {
+        let mut deck = *self;
+        for x in 0..board.width() {
+            for y in 0..board.top(x) {
+                let count = &mut deck.counts[board.get(x, y).unwrap().0];
+                *count = count.checked_sub(1)?;
+            }
+        }
+        Some(deck)
+    }
+    // colorを1個取り出した残りを返す（残っていなければNone）
+    pub fn remove(&self, color: Color) -> Option<Self> {
+        let mut deck = *self;
+        deck.counts[color.0] = deck.counts[color.0].checked_sub(1)?;
+        Some(deck)
+    }
+    // キャッシュのキーに使用するビット表現
+    pub fn to_bits(&self) -> u64 {
+        u64::from_le_bytes(self.counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probability_search::*;
+
+    #[test]
+    fn remaining() {
+        let deck = Deck::new(&[2, 1, 3]);
+        let mut board = Board::with_size(2, 3, 3);
+        board.drop(0, Color::RED);
+        board.drop(1, Color::BLUE);
+        let rest = deck.remaining(&board).unwrap();
+        assert_eq!((rest.count(Color::RED), rest.count(Color::GREEN), rest.count(Color::BLUE)), (1, 1, 2));
+        assert_eq!(rest.total(), 4);
+        assert_ne!(rest.to_bits(), deck.to_bits());
+        assert_eq!(rest.remove(Color::RED).map(|d| d.count(Color::RED)), Some(0));
+        assert_eq!(rest.remove(Color::RED).unwrap().remove(Color::RED), None);
+        board.drop(1, Color::GREEN);
+        board.drop(1, Color::GREEN);
+        assert_eq!(deck.remaining(&board), None);
+    }
+}
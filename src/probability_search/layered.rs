@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use super::{Board, Color, Dealt, Key, Probability, Problem, Queue};
+
+// 積んだ個数ごとの到達しうる状態（メモ化のキーと、その状態を代表するBoard）
+type Layer = Vec<(Key, Board)>;
+
+// 積んだ個数が少ない状態から順に到達しうる状態を全て列挙し、積み終わった状態から逆順に1層ずつ確率を求める
+// 再帰を使わず、同じ層の状態は互いに依存しないため、層ごとにthread_num個のスレッドで分担して計算する
+// 状態の一覧は層ごとに配列で持ち、確率は計算中の層と1つ後の層の分だけ保持するため、メモリ使用量を状態数から見積もれる
+// 予告ありの問題には対応しない
+pub fn probability_layered<P: Probability>(n: usize, board: Board, problem: &Problem, thread_num: usize) -> P {
+    assert_eq!(problem.preview(), 0, "the layered solver does not support a preview");
+    assert!(thread_num > 0, "at least one thread is required");
+    if let Some(deck) = problem.remaining_deck(&board) {
+        assert!(deck.total() >= n, "only {} balls are left in the deck", deck.total());
+    }
+    if n == 0 {
+        return problem.terminal_value(&board);
+    }
+    // 積み終わった状態は、1つ前の層の確率を求める際にその場で判定するため列挙しない
+    let mut layers: Vec<Layer> = vec![vec![(layer_key(problem, &board), board)]];
+    for _ in 1..n {
+        let next = expand(layers.last().unwrap(), problem, thread_num);
+        layers.push(next);
+    }
+    // 積み終わる直前の層から順に、1つ後の層の確率を参照して確率を求める
+    let last = layers.pop().unwrap();
+    let mut values: HashMap<Key, P> = evaluate(&last, problem, thread_num, |next| problem.terminal_value(next));
+    while let Some(layer) = layers.pop() {
+        values = evaluate(&layer, problem, thread_num, |next| values[&layer_key(problem, next)].clone());
+    }
+    values.into_values().next().unwrap()
+}
+
+// 予告なしの問題で状態を区別するキー（筒の並びだけが異なる状態は同じキーになる）
+fn layer_key(problem: &Problem, board: &Board) -> Key {
+    problem.key(board, problem.remaining_deck(board).as_ref(), &Queue::default()).canonical()
+}
+
+// boardに配られうる色のcolorを積める各筒に入れた場合の次の状態を列挙する
+fn successors<'a>(board: &'a Board, color: Color) -> impl Iterator<Item = Board> + 'a {
+    (0..board.width())
+        .filter(|&x| board.top(x) < board.height())
+        .map(move |x| {
+            let mut next = *board;
+            next.drop(x, color);
+            next
+        })
+}
+
+// layerの各状態から1個積んで到達しうる状態を重複なく列挙する
+fn expand(layer: &Layer, problem: &Problem, thread_num: usize) -> Layer {
+    let chunk_size = layer.len().div_ceil(thread_num);
+    let parts: Vec<HashMap<Key, Board>> = std::thread::scope(|s| {
+        let handles: Vec<_> = layer.chunks(chunk_size)
+            .map(|chunk| s.spawn(move || {
+                let mut next = HashMap::new();
+                for (_, board) in chunk {
+                    let deck = problem.remaining_deck(board);
+                    for color in problem.palette.all().filter(|&c| problem.weight(c, deck.as_ref()) > 0.0) {
+                        for board in successors(board, color) {
+                            next.entry(layer_key(problem, &board)).or_insert(board);
+                        }
+                    }
+                }
+                next
+            }))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let mut parts = parts.into_iter();
+    let mut merged = parts.next().unwrap_or_default();
+    for part in parts {
+        for (key, board) in part {
+            merged.entry(key).or_insert(board);
+        }
+    }
+    merged.into_iter().collect()
+}
+
+// layerの各状態の確率を、1個積んだ次の状態の確率を返すvalue_ofから求める
+fn evaluate<P, F>(layer: &Layer, problem: &Problem, thread_num: usize, value_of: F) -> HashMap<Key, P>
+    where P: Probability, F: Fn(&Board) -> P + Sync
+{
+    let chunk_size = layer.len().div_ceil(thread_num);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = layer.chunks(chunk_size)
+            .map(|chunk| s.spawn(|| {
+                chunk.iter()
+                    .map(|(key, board)| {
+                        let deck = problem.remaining_deck(board);
+                        let mut dealt = Dealt::new(problem);
+                        for color in problem.palette.all() {
+                            let weight = problem.weight(color, deck.as_ref());
+                            if weight == 0.0 {
+                                continue;
+                            }
+                            // もっとも確率が高くなる筒に入れる
+                            let max = successors(board, color)
+                                .map(|next| value_of(&next))
+                                .fold(P::zero(), |max, p| if p > max { p } else { max });
+                            dealt = dealt.add(weight, max);
+                        }
+                        (*key, dealt.result(problem, deck.as_ref()))
+                    })
+                    .collect::<Vec<_>>()
+            }))
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use num::BigRational;
+
+    use crate::probability_search::*;
+
+    #[test]
+    fn layered() {
+        let problem = Problem::new(Palette::default(), 3);
+        let p: f64 = probability(7, problem.board(2, 4), &problem, &HashMapCache::with_len(0));
+        for thread_num in [1, 3] {
+            let q: f64 = probability_layered(7, problem.board(2, 4), &problem, thread_num);
+            assert!((p - q).abs() < 1e-12, "{} != {}", p, q);
+        }
+        // 色の対称性、山札、敵対的な配り手、厳密な分数でも再帰による計算と一致する
+        let variants = [
+            problem.clone().with_symmetry(true),
+            problem.clone().with_deck(&[3, 3, 2]),
+            problem.clone().with_adversary(true),
+            problem.clone().with_condition(Box::new(Run::horizontal(2))),
+        ];
+        for problem in &variants {
+            let p: BigRational = probability(7, problem.board(2, 4), problem, &HashMapCache::with_len(0));
+            assert_eq!(probability_layered::<BigRational>(7, problem.board(2, 4), problem, 2), p);
+        }
+        // 途中まで積んだ状態からも求められる
+        let mut board = problem.board(2, 4);
+        board.drop(0, Color::RED);
+        board.drop(1, Color::RED);
+        let p: f64 = probability(5, board, &problem, &HashMapCache::with_len(0));
+        assert!((probability_layered::<f64>(5, board, &problem, 2) - p).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,770 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::marker::{Sync, Send};
+use std::str::FromStr;
+
+use num::BigRational;
+
+use crate::benchmark;
+use crate::random::XorShift;
+
+mod adversary;
+mod advisor;
+mod board;
+mod cache;
+mod color;
+mod condition;
+mod deck;
+mod layered;
+mod policy;
+mod position;
+mod preview;
+mod score;
+mod simulation;
+mod tasks;
+mod value;
+
+pub use adversary::{forcing_strategy, is_forced_win};
+pub use advisor::{play, Dealer, GameRecord, Turn};
+pub use board::{Board, BoardError, MAX_SYMMETRIC_COLORS, MAX_WIDTH};
+pub use cache::{AtomicCache, Cache, HashMapCache, Key, MutexCache, NoCache, RefCellCache, RwLockCache, ShardedCache};
+pub use color::{Color, Palette};
+pub use condition::{parse_condition, AllTubes, And, ColorCount, Direction, Not, Or, Run, WinCondition};
+pub use deck::{Deck, MAX_DECK_COLORS};
+pub use layered::probability_layered;
+pub use policy::{best_moves, evaluate_moves, policy_table, write_policy_csv, Move, PolicyEntry};
+pub use position::{analyze_position, Analysis, PositionError};
+pub use preview::{evaluate_moves_with_queue, probability_with_queue, Queue, MAX_PREVIEW, MAX_PREVIEW_COLORS};
+pub use simulation::{simulate, simulate_parallel, Estimate, Strategy};
+pub use tasks::probability_pooled;
+pub use score::{parse_score, RunScore, Score, Sum};
+pub use value::{Moments, Probability};
+
+/*
+解きたい問題
+毎回ランダムに配られる3色のカラーボールを2つの筒に1個ずつ入れていきます。
+1つの筒には最大6個のカラーボールを1列に積むことができます。
+12個のカラーボールを2つの筒に入れ終わった時点で、いずれかまたは両方の筒において
+同色のカラーボールが4つ以上接しているようにカラーボールを積める確率を求めてください。
+なお、手元のカラーボールを積むまで次にどの色が来るかは見ることができないものとし、
+カラーボールをどちらの筒に入れるかは求める確率が最大となるように選択する（その時点での最善手を選択する）ものとします。
+ */
+
+// 確率計算に渡す問題の定義
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub palette: Palette, // 配られるカラーボールの色
+    condition: Arc<dyn WinCondition>, // 積み終わった時点で満たすべき条件
+    score: Option<Arc<dyn Score>>, // 設定した場合は条件を満たす確率の代わりに得点の期待値を求める
+    ordered: bool, // 条件か得点が筒の並びによる場合はtrue（呼び出しごとに動的ディスパッチしないよう保持しておく）
+    // trueの場合、色の付け替えだけが異なるBoardを同じ状態としてメモ化する
+    // 各色が等確率で配られ、条件が色によらない場合にのみ結果が変わらない
+    pub symmetry: bool,
+    weights: Option<Vec<f64>>, // 各色が配られる確率の重み（Noneなら等確率）
+    deck: Option<Deck>, // 山札モードの袋の中身（Boardに積まれた分を含む。Noneなら配られる色は毎回独立）
+    preview: usize, // 手元のカラーボールの次から予告されるカラーボールの個数
+    adversarial: bool, // trueの場合、配り手は確率がもっとも低くなる色を選んで配る
+    namespace: u64, // キャッシュのキーの名前空間（問題の定義のハッシュ値）
+}
+
+impl Problem {
+    // いずれかの筒に同色のカラーボールがconnection_size個以上連続して積まれていれば満たす問題を作成する
+    pub fn new(palette: Palette, connection_size: usize) -> Self {
+        let mut problem = Self {
+            palette,
+            condition: Arc::new(Run::vertical(connection_size)),
+            score: None,
+            ordered: false,
+            symmetry: false,
+            weights: None,
+            deck: None,
+            preview: 0,
+            adversarial: false,
+            namespace: 0,
+        };
+        problem.update_namespace();
+        problem
+    }
+    // 満たすべき条件をconditionに置き換える
+    pub fn with_condition(mut self, condition: Box<dyn WinCondition>) -> Self {
+        assert!(!self.symmetry || (condition.is_color_symmetric() && condition.is_tube_symmetric()),
+                "symmetry reduction requires a condition independent of colors and tube order");
+        self.condition = Arc::from(condition);
+        self.ordered = !self.is_tube_symmetric();
+        self.update_namespace();
+        self
+    }
+    pub fn condition(&self) -> &dyn WinCondition {
+        self.condition.as_ref()
+    }
+    // 条件を満たす確率の代わりに、積み終わった時点のscoreの期待値を最大化する
+    pub fn with_score(mut self, score: Box<dyn Score>) -> Self {
+        assert!(!self.symmetry || (score.is_color_symmetric() && score.is_tube_symmetric()),
+                "symmetry reduction requires a score independent of colors and tube order");
+        self.score = Some(Arc::from(score));
+        self.ordered = !self.is_tube_symmetric();
+        self.update_namespace();
+        self
+    }
+    pub fn score(&self) -> Option<&dyn Score> {
+        self.score.as_deref()
+    }
+    // 条件と得点が筒の並びによらない場合はtrueを返す
+    fn is_tube_symmetric(&self) -> bool {
+        self.condition.is_tube_symmetric() && self.score.as_ref().is_none_or(|s| s.is_tube_symmetric())
+    }
+    // 条件と得点が色の付け替えによらない場合はtrueを返す
+    fn is_color_symmetric(&self) -> bool {
+        self.condition.is_color_symmetric() && self.score.as_ref().is_none_or(|s| s.is_color_symmetric())
+    }
+    // 条件が筒の並びによらない場合は筒を並べ替えたBoardを、そうでなければboardをそのまま返す
+    pub fn canonical(&self, board: &Board) -> Board {
+        if !self.ordered {
+            board.canonical()
+        } else {
+            *board
+        }
+    }
+    // 積み終わったboardが条件を満たしていればtrueを返す
+    pub fn is_satisfied(&self, board: &Board) -> bool {
+        self.condition.is_satisfied(board)
+    }
+    // 積み終わったboardの値（得点を設定していればその得点、そうでなければ条件を満たすなら1、満たさないなら0）
+    pub(super) fn terminal_value<P: Probability>(&self, board: &Board) -> P {
+        match &self.score {
+            Some(score) => P::from_score(score.score(board)),
+            None if self.is_satisfied(board) => P::one(),
+            None => P::zero(),
+        }
+    }
+    // 色の付け替えによる対称性を利用して状態数を削減する
+    pub fn with_symmetry(mut self, symmetry: bool) -> Self {
+        assert!(!symmetry || self.palette.len() <= MAX_SYMMETRIC_COLORS,
+                "symmetry reduction supports at most {} colors", MAX_SYMMETRIC_COLORS);
+        assert!(!symmetry || self.is_uniform(), "symmetry reduction requires uniform colors");
+        assert!(!symmetry || self.preview == 0, "symmetry reduction cannot be combined with a preview");
+        assert!(!symmetry || (self.is_color_symmetric() && self.is_tube_symmetric()),
+                "symmetry reduction requires a condition independent of colors and tube order");
+        self.symmetry = symmetry;
+        self
+    }
+    // 各色が配られる確率をweightsの比に設定する（合計が1である必要はない）
+    // 全ての重みが等しい場合は等確率として扱うため、重みを指定しない場合と同じ結果になる
+    pub fn with_weights(mut self, weights: &[f64]) -> Self {
+        assert_eq!(weights.len(), self.palette.len(), "a weight is required for each color");
+        assert!(weights.iter().all(|w| w.is_finite() && *w >= 0.0), "weights must be non-negative");
+        assert!(weights.iter().sum::<f64>() > 0.0, "at least one weight must be positive");
+        if weights.iter().all(|&w| w == weights[0]) {
+            self.weights = None;
+        } else {
+            assert!(!self.symmetry, "symmetry reduction requires uniform colors");
+            assert!(self.deck.is_none(), "weights cannot be combined with a deck");
+            self.weights = Some(weights.to_vec());
+        }
+        self.update_namespace();
+        self
+    }
+    // 配り手が色をランダムに選ぶ代わりに、確率（得点を設定した場合は得点）がもっとも低くなる色を選ぶようにする
+    // 確率は最悪の場合の値になり、1であれば配り手がどの色を選んでも条件を満たせる（必勝）
+    // 重みが0の色や袋に残っていない色は配られない
+    pub fn with_adversary(mut self, adversarial: bool) -> Self {
+        self.adversarial = adversarial;
+        self.update_namespace();
+        self
+    }
+    pub fn is_adversarial(&self) -> bool {
+        self.adversarial
+    }
+    // 色数、条件、得点、配り手、色の重み、山札と予告の個数からキャッシュのキーの名前空間を求める
+    // 同じキャッシュを共有する異なる問題の値を取り違えないよう、確率を変える設定は全て含める
+    fn update_namespace(&mut self) {
+        let mut hasher = DefaultHasher::new();
+        self.palette.len().hash(&mut hasher);
+        format!("{:?}", self.condition).hash(&mut hasher);
+        format!("{:?}", self.score).hash(&mut hasher);
+        self.adversarial.hash(&mut hasher);
+        for w in self.weights.iter().flatten() {
+            w.to_bits().hash(&mut hasher);
+        }
+        self.deck.hash(&mut hasher);
+        self.preview.hash(&mut hasher);
+        self.namespace = hasher.finish();
+    }
+    // 袋から取り出したカラーボールを戻さずに配る山札モードにする
+    // countsはゲーム開始時の袋に入っている色ごとの個数で、配られる確率は袋に残っている個数の比になる
+    pub fn with_deck(mut self, counts: &[usize]) -> Self {
+        assert_eq!(counts.len(), self.palette.len(), "a count is required for each color");
+        assert!(self.weights.is_none(), "weights cannot be combined with a deck");
+        assert!(!self.symmetry, "symmetry reduction requires uniform colors");
+        self.deck = Some(Deck::new(counts));
+        self.update_namespace();
+        self
+    }
+    // 手元のカラーボールを積む前に、次からk個のカラーボールの色が予告されるようにする
+    pub fn with_preview(mut self, k: usize) -> Self {
+        assert!(k <= MAX_PREVIEW, "at most {} balls can be previewed", MAX_PREVIEW);
+        assert!(k == 0 || self.palette.len() <= MAX_PREVIEW_COLORS,
+                "a preview supports at most {} colors", MAX_PREVIEW_COLORS);
+        assert!(k == 0 || !self.symmetry, "symmetry reduction cannot be combined with a preview");
+        self.preview = k;
+        self.update_namespace();
+        self
+    }
+    pub fn preview(&self) -> usize {
+        self.preview
+    }
+    // 全ての色が毎回等確率で配られる場合はtrueを返す
+    pub fn is_uniform(&self) -> bool {
+        self.weights.is_none() && self.deck.is_none()
+    }
+    // ゲーム開始時の袋の中身（山札モードでなければNone）
+    pub fn deck(&self) -> Option<&Deck> {
+        self.deck.as_ref()
+    }
+    // boardの状態で袋に残っているカラーボール（山札モードでなければNone）
+    pub fn remaining_deck(&self, board: &Board) -> Option<Deck> {
+        self.deck.map(|deck| deck.remaining(board).expect("board has more balls than the deck"))
+    }
+    // boardに積まれた分とqueueで配られた分を除いて袋に残っているカラーボール（山札モードでなければNone）
+    fn remaining_deck_after(&self, board: &Board, queue: &Queue) -> Option<Deck> {
+        self.remaining_deck(board).map(|deck| {
+            queue.iter().fold(deck, |deck, color| deck.remove(color).expect("queue has more balls than the deck"))
+        })
+    }
+    // boardの状態で次にcolorが配られる確率
+    pub fn color_probability(&self, color: Color, board: &Board) -> f64 {
+        let deck = self.remaining_deck(board);
+        self.weight(color, deck.as_ref()) / self.total_weight(deck.as_ref())
+    }
+    // 問題で定義された確率に従って、boardの状態で次に配られる色をランダムに選ぶ
+    // queueには配られたがまだ積まれていないカラーボール（予告ありの場合の手元と予告）を与える
+    pub fn sample_color(&self, board: &Board, queue: &Queue, rng: &mut XorShift) -> Color {
+        if let Some(deck) = self.remaining_deck_after(board, queue) {
+            let mut r = rng.gen_range(deck.total());
+            for color in self.palette.all() {
+                if r < deck.count(color) {
+                    return color;
+                }
+                r -= deck.count(color);
+            }
+            unreachable!();
+        }
+        match &self.weights {
+            None => Color(rng.gen_range(self.palette.len())),
+            Some(weights) => {
+                let mut r = rng.next_f64() * self.total_weight(None);
+                for color in self.palette.all() {
+                    if r < weights[color.0] {
+                        return color;
+                    }
+                    r -= weights[color.0];
+                }
+                // 丸め誤差で最後まで到達した場合は重みが正の最後の色とする
+                self.palette.all().filter(|c| weights[c.0] > 0.0).last().unwrap()
+            }
+        }
+    }
+    // 正規化前の重み（山札モードでは袋に残っている個数、等確率の場合は1）
+    pub(super) fn weight(&self, color: Color, deck: Option<&Deck>) -> f64 {
+        match deck {
+            Some(deck) => deck.count(color) as f64,
+            None => self.weights.as_ref().map_or(1.0, |weights| weights[color.0]),
+        }
+    }
+    pub(super) fn total_weight(&self, deck: Option<&Deck>) -> f64 {
+        match deck {
+            Some(deck) => deck.total() as f64,
+            None => self.weights.as_ref().map_or(self.palette.len() as f64, |weights| weights.iter().sum()),
+        }
+    }
+    // メモ化のキー（山札モードでは袋に残っているカラーボールの構成、予告ありでは予告の列を含む）
+    fn key(&self, board: &Board, deck: Option<&Deck>, queue: &Queue) -> Key {
+        let board = if self.symmetry {
+            board.canonical_colors(self.palette.len())
+        } else {
+            *board
+        };
+        Key {
+            board,
+            ordered: self.ordered,
+            namespace: self.namespace,
+            deck: deck.map_or(0, Deck::to_bits),
+            queue: queue.to_bits(),
+        }
+    }
+    // この問題の色数に対応した空のBoardを作成する
+    pub fn board(&self, width: usize, height: usize) -> Board {
+        Board::with_size(width, height, self.palette.len())
+    }
+    // width * heightのBoardの全状態を格納できるキャッシュの長さ（usizeに収まらない場合はNone）
+    pub fn cache_size(&self, width: usize, height: usize) -> Option<usize> {
+        let bits = u32::try_from(width * height * self.palette.bits()).ok()?;
+        1usize.checked_shl(bits)
+    }
+}
+
+// 条件を満たす確率を求める関数
+// Cacheを使用する場合と使用しない場合とで共通の実装になっているが
+// Cacheトレイト実装型として引数に与えられる型は静的ディスパッチで決定されるため
+// NoCacheを与えた場合はコンパイラの最適化によりキャッシュ処理のコードは削除される
+// 予告ありの場合は、予告される色で場合分けした確率の平均を求める（preview::fill_queue）
+// 確率の型Pはキャッシュの型から決まる（f64のほか、厳密な分数で求めるBigRationalを使用できる）
+pub fn probability<P: Probability>(n: usize, board: Board, problem: &Problem, cache: &impl Cache<P>) -> P {
+    if problem.preview > 0 {
+        return preview::fill_queue(n, board, Queue::default(), problem, cache);
+    }
+    // 最後まで積み終わった状態で、問題の条件（既定では設定された連結数以上の連結）を満たすか判定する
+    // 条件を満たしている場合は確率1、満たしていない場合は確率0を返す（得点を設定した場合は得点を返す）
+    if n == 0 {
+        problem.terminal_value(&board)
+    } else {
+        // 山札モードでは袋に残っているカラーボールから配られる
+        let deck = problem.remaining_deck(&board);
+        if let Some(deck) = &deck {
+            assert!(deck.total() >= n, "only {} balls are left in the deck", deck.total());
+        }
+        // キャッシュがある場合はキャッシュの値を返す（NoCacheの場合は常に存在しない）
+        let key = problem.key(&board, deck.as_ref(), &Queue::default());
+        if let Some(c) = cache.get(&key) {
+            return c;
+        }
+        let mut dealt = Dealt::new(problem);
+        // ランダムに来るn色の場合の確率をそれぞれ計算し、色の重みを掛けてdealtに足していく
+        for color in problem.palette.all() {
+            let weight = problem.weight(color, deck.as_ref());
+            if weight == 0.0 {
+                continue;
+            }
+            let mut max = P::zero();
+            // どの筒に入れるかは、入れた場合にもっとも確率が高くなる方に入れるという判断をする
+            // もっとも確率が高くなる方に入れた場合の確率がmax変数に入る
+            for x in 0..board.width() {
+                // 満杯の筒には入れられない
+                let mut board = board;
+                if board.try_drop(x, color).is_err() {
+                    continue;
+                }
+                let p = probability(n - 1, board, problem, cache);
+                if p > max {
+                    max = p;
+                }
+            }
+            dealt = dealt.add(weight, max);
+        }
+        // 重みの合計で割って得られる確率の加重平均が求める確率（等確率なら色数で割った平均値）
+        // 配り手が敵対的な場合は、色ごとの確率の最小値が求める確率
+        let p = dealt.result(problem, deck.as_ref());
+        // 得られた確率はキャッシュにも格納しておく（NoCacheの場合は何もしない）
+        cache.set(&key, p.clone());
+        p
+    }
+}
+
+// threaded_n回目の呼び出しまでスレッドを立ち上げて並列計算を実施
+pub fn probability_parallel<P, T>(n: usize, threaded_n: usize, board: Board,
+                                  problem: &Problem, cache: &Arc<T>) -> P
+    where P: Probability, T: Cache<P> + Sync + Send + 'static
+{
+    if problem.preview > 0 {
+        // 予告ありの場合は、最初に予告される色の組み合わせごとにスレッドを立ち上げる
+        preview::fill_queue_parallel(n, threaded_n, board, Queue::default(), problem, cache)
+    } else if n <= 1 || threaded_n == 0 {
+        // 以降の計算は各スレッドにおいて直列処理を呼び出して処理を続行
+        probability(n, board, problem, cache.as_ref())
+    } else {
+        let deck = problem.remaining_deck(&board);
+        let mut handles_map = vec![];
+        for color in problem.palette.all() {
+            let weight = problem.weight(color, deck.as_ref());
+            if weight == 0.0 {
+                continue;
+            }
+            let mut handles = vec![];
+            for x in 0..board.width() {
+                let mut board = board;
+                if board.try_drop(x, color).is_err() {
+                    continue;
+                }
+                let problem = problem.clone();
+                let cache = Arc::clone(cache);
+                let handle = std::thread::spawn(move || {
+                    //println!("thread spawned: n = {}, x = {}, color = {}", n, x, color.0);
+                    probability_parallel(n - 1, threaded_n - 1, board, &problem, &cache)
+                });
+                handles.push(handle);
+            }
+            handles_map.push((weight, handles));
+        }
+        let mut dealt = Dealt::new(problem);
+        for (weight, handles) in handles_map {
+            let mut max = P::zero();
+            for handle in handles {
+                let p = handle.join().unwrap();
+                if p > max {
+                    max = p;
+                }
+            }
+            dealt = dealt.add(weight, max);
+        }
+        dealt.result(problem, deck.as_ref())
+    }
+}
+
+// 配られる色ごとの確率の集計
+// ランダムに配られる場合は色の重みによる加重平均、敵対的に配られる場合は最小値を求める
+pub(super) struct Dealt<P> {
+    adversarial: bool,
+    sum: P,
+    min: Option<P>,
+}
+
+impl<P: Probability> Dealt<P> {
+    pub(super) fn new(problem: &Problem) -> Self {
+        Self { adversarial: problem.adversarial, sum: P::zero(), min: None }
+    }
+    // 重みweightの色が配られた場合の確率pを加える
+    pub(super) fn add(self, weight: f64, p: P) -> Self {
+        if self.adversarial {
+            let min = match self.min {
+                Some(min) if min <= p => min,
+                _ => p,
+            };
+            Self { min: Some(min), ..self }
+        } else {
+            Self { sum: self.sum + P::from_weight(weight) * p, ..self }
+        }
+    }
+    pub(super) fn result(self, problem: &Problem, deck: Option<&Deck>) -> P {
+        if self.adversarial {
+            self.min.unwrap_or_else(P::zero)
+        } else {
+            self.sum / P::from_weight(problem.total_weight(deck))
+        }
+    }
+}
+
+// 問題設定（盤面の大きさ、積む総数、色と連結数）と並列化の設定
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub width: usize, // 筒の個数
+    pub height: usize, // 1本の筒に積めるカラーボール最大数
+    pub n: usize, // カラーボールを積む総数
+    pub threaded_n: usize, // スレッドを立ち上げる再帰の深さ（2なら色数3、筒数2のとき6 + 6 * 6 = 42スレッド）
+    pub problem: Problem,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { width: 2, height: 6, n: 12, threaded_n: 2, problem: Problem::new(Palette::default(), 4) }
+    }
+}
+
+// ベンチマークで使用するキャッシュの種類
+// Noneは直列・並列の両方、RefCell / HashMapは直列、Mutex / RwLock / Atomic / Shardedは並列で計測する
+// Pooledはシャード分割HashMapキャッシュを使用するprobability_pooledを、Layeredはキャッシュの代わりに
+// 層ごとの確率を保持するprobability_layeredを、それぞれ使用可能なCPU数のスレッドで計測する
+// RefCell / Mutex / RwLock / Atomicは全状態分の配列を確保するため、Problem::cache_sizeがNoneとなる大きなBoardには使用できない
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Backend {
+    None,
+    RefCell,
+    Mutex,
+    RwLock,
+    Atomic,
+    HashMap,
+    Sharded,
+    Pooled,
+    Layered,
+}
+
+impl Backend {
+    pub fn all() -> Vec<Self> {
+        vec![Self::None, Self::RefCell, Self::Mutex, Self::RwLock, Self::Atomic, Self::HashMap, Self::Sharded,
+             Self::Pooled, Self::Layered]
+    }
+    // 予告ありの問題を計算できればtrueを返す
+    // 密なキャッシュはBoard::serializeを添字とし予告の列を区別しないため、予告ありの問題では誤った値を返す
+    pub fn supports_preview(&self) -> bool {
+        !self.is_dense() && !matches!(self, Self::Pooled | Self::Layered)
+    }
+    // 全状態分の配列を確保するキャッシュであればtrueを返す
+    pub fn is_dense(&self) -> bool {
+        matches!(self, Self::RefCell | Self::Mutex | Self::RwLock | Self::Atomic)
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "refcell" => Ok(Self::RefCell),
+            "mutex" => Ok(Self::Mutex),
+            "rwlock" => Ok(Self::RwLock),
+            "atomic" => Ok(Self::Atomic),
+            "hashmap" => Ok(Self::HashMap),
+            "sharded" => Ok(Self::Sharded),
+            "pooled" => Ok(Self::Pooled),
+            "layered" => Ok(Self::Layered),
+            _ => Err(format!("unknown cache backend: {}", s)),
+        }
+    }
+}
+
+pub fn calc_probabilities(settings: &Settings, backends: &[Backend],
+                          config: &benchmark::Config, format: benchmark::Format) {
+    let Settings { width, height, n, threaded_n, ref problem } = *settings;
+    let cache_size = problem.cache_size(width, height);
+    let dense_cache_size = || cache_size.expect("board is too large for a dense cache");
+    let board = problem.board(width, height);
+    let thread_num = std::thread::available_parallelism().map_or(1, |n| n.get());
+    // 予告に対応しない種類で計算すると誤った値になるため受け付けない
+    if problem.preview() > 0 {
+        if let Some(backend) = backends.iter().find(|b| !b.supports_preview()) {
+            panic!("{:?} does not support a preview", backend);
+        }
+    }
+    eprintln!("cache_size = {}", cache_size.map_or("-".to_string(), |size| size.to_string()));
+    // 処理時間にばらつきが生じるためそれぞれconfig.repeat回計測
+    let mut results = vec![];
+    for backend in backends {
+        match backend {
+            Backend::None => {
+                // 直列処理キャッシュ無し
+                results.push(benchmark::bench("Serial without cache", config, None, || {
+                    probability(n, board, problem, &NoCache::with_len(0))
+                }));
+                // 並列処理キャッシュ無し
+                results.push(benchmark::bench("Parallel without cache", config, None, || {
+                    let cache = Arc::new(NoCache::with_len(0));
+                    probability_parallel(n, threaded_n, board, problem, &cache)
+                }));
+            }
+            // 直列処理RefCellキャッシュ使用
+            Backend::RefCell => results.push(benchmark::bench_with_setup("Serial with RefCellCache", config, None,
+                || RefCellCache::with_len(dense_cache_size()),
+                |cache| probability(n, board, problem, cache))),
+            // 並列処理Mutexキャッシュ使用
+            Backend::Mutex => results.push(benchmark::bench_with_setup("Parallel with MutexCache", config, None,
+                || Arc::new(MutexCache::with_len(dense_cache_size())),
+                |cache| probability_parallel(n, threaded_n, board, problem, cache))),
+            // 並列処理RwLockキャッシュ使用
+            Backend::RwLock => results.push(benchmark::bench_with_setup("Parallel with RwLockCache", config, None,
+                || Arc::new(RwLockCache::with_len(dense_cache_size())),
+                |cache| probability_parallel(n, threaded_n, board, problem, cache))),
+            // 並列処理ロックフリーAtomicキャッシュ使用
+            Backend::Atomic => results.push(benchmark::bench_with_setup("Parallel with AtomicCache", config, None,
+                || Arc::new(AtomicCache::with_len(dense_cache_size())),
+                |cache| probability_parallel(n, threaded_n, board, problem, cache))),
+            // 直列処理HashMapキャッシュ使用
+            Backend::HashMap => results.push(benchmark::bench_with_setup("Serial with HashMapCache", config, None,
+                || HashMapCache::<f64>::with_len(0),
+                |cache| probability(n, board, problem, cache))),
+            // 並列処理シャード分割HashMapキャッシュ使用
+            Backend::Sharded => results.push(benchmark::bench_with_setup("Parallel with ShardedCache", config, None,
+                || Arc::new(ShardedCache::<f64>::with_len(0)),
+                |cache| probability_parallel(n, threaded_n, board, problem, cache))),
+            // スレッドプールで並列処理シャード分割HashMapキャッシュ使用
+            Backend::Pooled => results.push(benchmark::bench_with_setup("Pooled with ShardedCache", config, None,
+                || Arc::new(ShardedCache::<f64>::with_len(0)),
+                |cache| probability_pooled(n, board, problem, cache, thread_num))),
+            // 層ごとに並列処理する動的計画法
+            Backend::Layered => results.push(benchmark::bench("Parallel layered DP", config, None,
+                || probability_layered::<f64>(n, board, problem, thread_num))),
+        }
+    }
+    if problem.score().is_some() {
+        // 得点を設定した場合は期待値と分散を表示する
+        let cache = HashMapCache::<Moments>::with_len(0);
+        let moments = probability(n, board, problem, &cache);
+        eprintln!("expected score = {}, variance = {}", moments.mean, moments.variance());
+        eprintln!("reachable states = {}", cache.len());
+    } else {
+        let cache = HashMapCache::<f64>::with_len(0);
+        let p = probability(n, board, problem, &cache);
+        eprintln!("p = {}", p);
+        if problem.is_adversarial() {
+            eprintln!("forced win = {}", p == 1.0);
+        }
+        eprintln!("reachable states = {}", cache.len());
+    }
+    print!("{}", benchmark::report(&results, format));
+}
+
+// 厳密な分数で確率を求め、f64で求めた確率との差を表示する
+pub fn verify_exact(settings: &Settings) -> BigRational {
+    let Settings { width, height, n, threaded_n, ref problem } = *settings;
+    let board = problem.board(width, height);
+    let p: f64 = probability_parallel(n, threaded_n, board, problem, &Arc::new(ShardedCache::with_len(0)));
+    let exact: BigRational = probability_parallel(n, threaded_n, board, problem, &Arc::new(ShardedCache::with_len(0)));
+    eprintln!("exact p = {}", exact);
+    eprintln!("|p - exact| = {:e}", (p - exact.to_f64()).abs());
+    exact
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probability_search::*;
+
+    #[test]
+    fn palette() {
+        assert_eq!(Palette::with_len(1).bits(), 1);
+        assert_eq!(Palette::with_len(3).bits(), 2);
+        assert_eq!(Palette::with_len(4).bits(), 3);
+        assert_eq!(Palette::default().name(Color::BLUE), "Blue");
+        assert_eq!(Palette::with_names(&["A", "B"]).all().collect::<Vec<_>>(), vec![Color(0), Color(1)]);
+    }
+    #[test]
+    fn number_of_colors() {
+        let board = Board::with_size(2, 3, 1);
+        assert_eq!(probability(6, board, &Problem::new(Palette::with_len(1), 3), &NoCache::with_len(0)), 1.0);
+        for colors in 2..=5 {
+            let problem = Problem::new(Palette::with_len(colors), 3);
+            let cache = RefCellCache::with_len(problem.cache_size(2, 3).unwrap());
+            let p = probability(6, problem.board(2, 3), &problem, &cache);
+            let q = probability(6, problem.board(2, 3), &problem, &NoCache::with_len(0));
+            let r = probability_parallel(6, 2, problem.board(2, 3), &problem, &Arc::new(NoCache::with_len(0)));
+            let atomic = Arc::new(AtomicCache::with_len(problem.cache_size(2, 3).unwrap()));
+            let s = probability_parallel(6, 2, problem.board(2, 3), &problem, &atomic);
+            assert!(p > 0.0 && p <= 1.0);
+            assert_eq!(p, q);
+            assert_eq!(p, r);
+            assert_eq!(p, s);
+            // 格納済みの値はロックせずに読み出せる
+            assert_eq!(probability(6, problem.board(2, 3), &problem, atomic.as_ref()), p);
+        }
+    }
+    #[test]
+    fn color_symmetry() {
+        for (colors, width, height, n, connection_size) in [(2, 2, 4, 8, 3), (3, 2, 6, 12, 4), (4, 3, 3, 7, 3)] {
+            let problem = Problem::new(Palette::with_len(colors), connection_size);
+            let symmetric = problem.clone().with_symmetry(true);
+            let cache = HashMapCache::<f64>::with_len(0);
+            let symmetric_cache = HashMapCache::<f64>::with_len(0);
+            let p = probability(n, problem.board(width, height), &problem, &cache);
+            let q = probability(n, symmetric.board(width, height), &symmetric, &symmetric_cache);
+            assert!((p - q).abs() < 1e-12, "{} != {}", p, q);
+            assert!(symmetric_cache.len() < cache.len());
+            let r = probability_parallel(n, 2, symmetric.board(width, height), &symmetric,
+                                         &Arc::new(ShardedCache::<f64>::with_len(0)));
+            assert!((p - r).abs() < 1e-12);
+        }
+    }
+    #[test]
+    fn weighted_colors() {
+        let uniform = Problem::new(Palette::default(), 3);
+        let equal = uniform.clone().with_weights(&[2.0, 2.0, 2.0]);
+        assert!(equal.is_uniform());
+        let p = probability(7, uniform.board(2, 4), &uniform, &NoCache::with_len(0));
+        assert_eq!(probability(7, equal.board(2, 4), &equal, &NoCache::with_len(0)), p);
+        // 赤しか配られなければ必ず条件を満たす
+        let red = uniform.clone().with_weights(&[1.0, 0.0, 0.0]);
+        assert_eq!(probability(7, red.board(2, 4), &red, &NoCache::with_len(0)), 1.0);
+        // 偏りがあるほど同色が揃いやすい。直列と並列で結果が一致する
+        let skewed = uniform.clone().with_weights(&[0.6, 0.3, 0.1]);
+        assert!((skewed.color_probability(Color::RED, &skewed.board(2, 4)) - 0.6).abs() < 1e-12);
+        let cache = Arc::new(ShardedCache::<f64>::with_len(0));
+        let q = probability(7, skewed.board(2, 4), &skewed, cache.as_ref());
+        assert!(q > p && q < 1.0);
+        let r = probability_parallel(7, 2, skewed.board(2, 4), &skewed, &Arc::new(ShardedCache::<f64>::with_len(0)));
+        assert!((q - r).abs() < 1e-12);
+        // 同じキャッシュを共有しても名前空間が異なるため結果が混ざらない
+        assert_eq!(probability(7, uniform.board(2, 4), &uniform, cache.as_ref()), p);
+    }
+    #[test]
+    fn finite_deck() {
+        let problem = Problem::new(Palette::default(), 2);
+        // 3色が1個ずつでは同色が揃わず、同色が2個あれば必ず揃えられる
+        let deck = problem.clone().with_deck(&[1, 1, 1]);
+        assert_eq!(probability(3, deck.board(2, 3), &deck, &NoCache::with_len(0)), 0.0);
+        let deck = problem.clone().with_deck(&[2, 1, 0]);
+        assert_eq!(probability(3, deck.board(2, 3), &deck, &NoCache::with_len(0)), 1.0);
+        // 配られた分だけ袋の中身が変わる
+        let mut board = deck.board(2, 3);
+        board.drop(0, Color::RED);
+        assert_eq!(deck.color_probability(Color::RED, &board), 0.5);
+        assert_eq!(deck.color_probability(Color::BLUE, &board), 0.0);
+        // 袋が十分に大きければ毎回独立に配られる場合に近づく
+        let problem = Problem::new(Palette::default(), 3);
+        let p = probability(6, problem.board(2, 3), &problem, &HashMapCache::<f64>::with_len(0));
+        let deck = problem.clone().with_deck(&[100, 100, 100]);
+        let cache = Arc::new(ShardedCache::<f64>::with_len(0));
+        let q = probability(6, deck.board(2, 3), &deck, cache.as_ref());
+        assert!((p - q).abs() < 0.01 && p != q, "{} {}", p, q);
+        let r = probability_parallel(6, 2, deck.board(2, 3), &deck, &Arc::new(ShardedCache::<f64>::with_len(0)));
+        assert!((q - r).abs() < 1e-12);
+        assert_eq!(probability(6, problem.board(2, 3), &problem, cache.as_ref()), p);
+    }
+    #[test]
+    fn win_conditions() {
+        let vertical = Problem::new(Palette::default(), 3);
+        let horizontal = vertical.clone().with_condition(Box::new(Run::horizontal(3)));
+        let either = vertical.clone().with_condition(Box::new(Or(vec![Box::new(Run::vertical(3)),
+                                                                      Box::new(Run::horizontal(3))])));
+        let mut last = 0.0;
+        for problem in [&vertical, &horizontal, &either] {
+            // 筒の並びによる条件でもキャッシュの有無で結果が変わらない
+            let p = probability(6, problem.board(3, 3), problem, &NoCache::with_len(0));
+            let dense = RefCellCache::with_len(problem.cache_size(3, 3).unwrap());
+            assert_eq!(probability(6, problem.board(3, 3), problem, &dense), p);
+            assert_eq!(probability(6, problem.board(3, 3), problem, &HashMapCache::<f64>::with_len(0)), p);
+            let q = probability_parallel(6, 2, problem.board(3, 3), problem, &Arc::new(ShardedCache::<f64>::with_len(0)));
+            assert!((p - q).abs() < 1e-12);
+            assert!(p > 0.0 && p < 1.0);
+            last = f64::max(last, p);
+        }
+        // いずれかを満たせばよい条件は、それぞれの条件より満たしやすい
+        assert_eq!(last, probability(6, either.board(3, 3), &either, &NoCache::with_len(0)));
+        let never = vertical.clone().with_condition(Box::new(And(vec![Box::new(Run::vertical(3)),
+                                                                      Box::new(Not(Box::new(Run::vertical(1))))])));
+        assert_eq!(probability(6, never.board(3, 3), &never, &HashMapCache::<f64>::with_len(0)), 0.0);
+    }
+    #[test]
+    fn expected_score() {
+        // 1色だけなら積み方によらず各筒に3個の並びができる
+        let single = Problem::new(Palette::with_len(1), 3).with_score(parse_score("vertical:0,0,1", None).unwrap());
+        let moments = probability(6, single.board(2, 3), &single, &HashMapCache::<Moments>::with_len(0));
+        assert_eq!((moments.mean, moments.variance()), (2.0, 0.0));
+        let score = RunScore::new(Direction::Vertical, &[0.0, 1.0, 4.0]);
+        let problem = Problem::new(Palette::default(), 3).with_score(Box::new(score));
+        let p: f64 = probability(6, problem.board(2, 3), &problem, &HashMapCache::with_len(0));
+        let q: f64 = probability_parallel(6, 2, problem.board(2, 3), &problem, &Arc::new(ShardedCache::with_len(0)));
+        let moments = probability(6, problem.board(2, 3), &problem, &HashMapCache::<Moments>::with_len(0));
+        assert!(p > 1.0 && p < 8.0);
+        assert!((p - q).abs() < 1e-12 && (p - moments.mean).abs() < 1e-12);
+        assert!(moments.variance() > 0.0);
+        // 色の対称性を利用しても期待値は変わらず、勝敗の確率とはキャッシュを共有しても混ざらない
+        let symmetric = problem.clone().with_symmetry(true);
+        let cache = HashMapCache::<f64>::with_len(0);
+        assert!((probability(6, symmetric.board(2, 3), &symmetric, &cache) - p).abs() < 1e-12);
+        let plain = Problem::new(Palette::default(), 3);
+        assert_eq!(probability(6, plain.board(2, 3), &plain, &cache),
+                   probability(6, plain.board(2, 3), &plain, &NoCache::with_len(0)));
+    }
+    #[test]
+    fn sparse_caches() {
+        let problem = Problem::new(Palette::default(), 3);
+        let dense = probability(6, problem.board(2, 3), &problem, &RefCellCache::with_len(1 << 12));
+        let cache = HashMapCache::<f64>::with_len(0);
+        assert_eq!(probability(6, problem.board(2, 3), &problem, &cache), dense);
+        assert!(cache.len() > 0 && cache.len() < 1 << 12);
+        // 色数や山札の異なる問題でキャッシュを共有しても互いの値を取り違えない
+        let shared = HashMapCache::<f64>::with_len(0);
+        let variants = [problem.clone(), Problem::new(Palette::with_len(2), 3), problem.clone().with_deck(&[2, 2, 2])];
+        let expected: Vec<f64> = variants.iter()
+            .map(|v| probability(6, v.board(2, 3), v, &HashMapCache::with_len(0)))
+            .collect();
+        for (v, p) in variants.iter().zip(&expected) {
+            assert_eq!(probability(6, v.board(2, 3), v, &shared), *p);
+        }
+        let cache = Arc::new(ShardedCache::<f64>::with_len(0));
+        assert_eq!(probability_parallel(6, 2, problem.board(2, 3), &problem, &cache), dense);
+        // 3本 × 高さ8の筒は密なキャッシュでは2^48要素が必要になる
+        let problem = Problem::new(Palette::default(), 4);
+        assert_eq!(problem.cache_size(3, 8), Some(1 << 48));
+        let p = probability(9, problem.board(3, 8), &problem, &HashMapCache::<f64>::with_len(0));
+        let q = probability_parallel(9, 2, problem.board(3, 8), &problem, &Arc::new(ShardedCache::<f64>::with_len(0)));
+        assert!(p > 0.0 && p < 1.0);
+        assert!((p - q).abs() < 1e-12);
+    }
+}
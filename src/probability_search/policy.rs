@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use super::{probability, Board, Cache, Color, Palette, Problem};
+
+// 同じ確率とみなす誤差（筒の中身が同じ場合などに最善手を全て列挙するため）
+const EPSILON: f64 = 1e-12;
+
+// x番目の筒にカラーボールを入れた場合に条件を満たす確率
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Move {
+    pub x: usize,
+    pub probability: f64,
+}
+
+// 残りn個（手元のカラーボールを含む）の時点でcolorのカラーボールが来たとき、積める各筒に入れた場合の確率を返す
+pub fn evaluate_moves(n: usize, board: Board, color: Color, problem: &Problem, cache: &impl Cache) -> Vec<Move> {
+    assert!(n > 0, "no ball is left to place");
+    assert_eq!(problem.preview(), 0, "use evaluate_moves_with_queue when balls are previewed");
+    (0..board.width())
+        .filter(|&x| board.top(x) < board.height())
+        .map(|x| {
+            let mut board = board;
+            board.drop(x, color);
+            Move { x, probability: probability(n - 1, board, problem, cache) }
+        })
+        .collect()
+}
+
+// 確率が最大となる筒（同率の場合は全て）を返す
+pub fn best_moves(n: usize, board: Board, color: Color, problem: &Problem, cache: &impl Cache) -> Vec<Move> {
+    let moves = evaluate_moves(n, board, color, problem, cache);
+    let max = moves.iter().map(|m| m.probability).fold(0.0, f64::max);
+    moves.into_iter().filter(|m| max - m.probability <= EPSILON).collect()
+}
+
+// 方策表の1行。boardの状態で残りn個のときにcolorが来た場合の各筒の確率
+#[derive(Clone, Debug)]
+pub struct PolicyEntry {
+    pub board: Board,
+    pub n: usize,
+    pub color: Color,
+    pub moves: Vec<Move>,
+}
+
+impl PolicyEntry {
+    // 確率が最大となる筒の番号
+    pub fn best(&self) -> Vec<usize> {
+        let max = self.moves.iter().map(|m| m.probability).fold(0.0, f64::max);
+        self.moves.iter().filter(|m| max - m.probability <= EPSILON).map(|m| m.x).collect()
+    }
+}
+
+// boardから残りn個を積む間に到達しうる全ての状態について方策表を作成する
+// 条件が筒の並びによらない場合、筒の並びだけが異なる状態は1つにまとめ、筒を並べ替えたBoard（Board::canonical）を代表として出力する
+pub fn policy_table(n: usize, board: Board, problem: &Problem, cache: &impl Cache) -> Vec<PolicyEntry> {
+    let mut entries = vec![];
+    let mut visited = HashSet::new();
+    let mut stack = vec![(problem.canonical(&board), n)];
+    while let Some((board, n)) = stack.pop() {
+        if n == 0 || !visited.insert(board) {
+            continue;
+        }
+        // 配られることのない色（重みが0の色や袋に残っていない色）の行は出力しない
+        for color in problem.palette.all().filter(|&c| problem.color_probability(c, &board) > 0.0) {
+            let moves = evaluate_moves(n, board, color, problem, cache);
+            for m in &moves {
+                let mut next = board;
+                next.drop(m.x, color);
+                stack.push((problem.canonical(&next), n - 1));
+            }
+            entries.push(PolicyEntry { board, n, color, moves });
+        }
+    }
+    entries
+}
+
+// 方策表をCSV形式で書き出す
+// boardの列はBoardの文字列表記（Board::parseで読み込める）とする
+// 最善手が複数ある場合は筒の番号を空白区切りで並べ、積めない筒の確率は空欄とする
+pub fn write_policy_csv(entries: &[PolicyEntry], palette: &Palette, width: usize,
+                        writer: &mut impl Write) -> io::Result<()> {
+    write!(writer, "board,balls_left,color,best")?;
+    for x in 0..width {
+        write!(writer, ",p{}", x)?;
+    }
+    writeln!(writer)?;
+    for entry in entries {
+        let best: Vec<String> = entry.best().iter().map(|x| x.to_string()).collect();
+        write!(writer, "{},{},{},{}", entry.board, entry.n,
+               palette.name(entry.color), best.join(" "))?;
+        for x in 0..width {
+            match entry.moves.iter().find(|m| m.x == x) {
+                Some(m) => write!(writer, ",{}", m.probability)?,
+                None => write!(writer, ",")?,
+            }
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probability_search::*;
+
+    #[test]
+    fn best_move_matches_probability() {
+        let problem = Problem::new(Palette::default(), 3);
+        let cache = HashMapCache::with_len(0);
+        let mut board = problem.board(2, 3);
+        board.drop(0, Color::RED);
+        board.drop(0, Color::RED);
+        // 赤が来たら赤が2個積まれた筒に入れれば必ず条件を満たす
+        let moves = best_moves(4, board, Color::RED, &problem, &cache);
+        assert_eq!(moves, vec![Move { x: 0, probability: 1.0 }]);
+        // 各色の最善手の確率の平均がその状態の確率に一致する
+        let p = probability(4, board, &problem, &cache);
+        let sum: f64 = problem.palette.all()
+            .map(|color| best_moves(4, board, color, &problem, &cache)[0].probability)
+            .sum();
+        assert!((p - sum / 3.0).abs() < 1e-12);
+    }
+    #[test]
+    fn policy_table_covers_reachable_states() {
+        let problem = Problem::new(Palette::with_len(2), 2);
+        let cache = HashMapCache::with_len(0);
+        let entries = policy_table(4, problem.board(2, 2), &problem, &cache);
+        // 方策表は状態ごとに色の数だけ行を持つ。空の状態は1つ、1個積んだ状態は筒の並べ替えを除いて2つ
+        assert_eq!(entries.iter().filter(|e| e.n == 4).count(), 2);
+        assert_eq!(entries.iter().filter(|e| e.n == 3).count(), 4);
+        assert!(entries.iter().all(|e| !e.best().is_empty()));
+        let mut csv = vec![];
+        write_policy_csv(&entries, &problem.palette, 2, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().next(), Some("board,balls_left,color,best,p0,p1"));
+        assert_eq!(csv.lines().count(), entries.len() + 1);
+        // boardの列は文字列表記から元のBoardに戻せる
+        for (line, entry) in csv.lines().skip(1).zip(&entries) {
+            let label = line.split(',').next().unwrap();
+            assert_eq!(Board::parse(label, problem.palette.len()), Ok(entry.board));
+        }
+        assert!(csv.lines().any(|line| line.starts_with("..|..,4,")));
+    }
+}
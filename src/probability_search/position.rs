@@ -0,0 +1,131 @@
+use std::fmt;
+
+use super::{evaluate_moves, probability, Board, BoardError, Cache, Color, HashMapCache, PolicyEntry, Problem};
+
+// 途中局面の整合性の検査に失敗した理由
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PositionError {
+    Board(BoardError), // 問題の色数でBoardを作り直せない
+    UnknownColor(Color), // 問題で配られない色のカラーボールが積まれている
+    TooManyBalls { n: usize, capacity: usize }, // 残りの個数が筒の空きに収まらない
+    DeckExhausted, // 袋に入っている個数より多く積まれている色がある
+    DeckTooSmall { n: usize, remaining: usize }, // 残りの個数を袋から配れない
+    PreviewUnsupported, // 予告ありの問題は解析できない
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Board(e) => write!(f, "{}", e),
+            Self::UnknownColor(color) => write!(f, "color {} is not dealt in this problem", color.0),
+            Self::TooManyBalls { n, capacity } => write!(f, "{} balls do not fit in {} empty slots", n, capacity),
+            Self::DeckExhausted => write!(f, "board has more balls than the deck"),
+            Self::DeckTooSmall { n, remaining } => {
+                write!(f, "{} balls cannot be dealt from the {} left in the deck", n, remaining)
+            }
+            Self::PreviewUnsupported => write!(f, "analyzing a position with a preview is not supported"),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+impl From<BoardError> for PositionError {
+    fn from(e: BoardError) -> Self {
+        Self::Board(e)
+    }
+}
+
+// 途中局面の解析結果
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub board: Board, // 問題の色数で作り直したBoard
+    pub n: usize,
+    pub probability: f64, // 残りn個を最善に積んだ場合に条件を満たす確率（得点を設定した場合は得点の期待値）
+    pub entries: Vec<PolicyEntry>, // 次に配られうる色ごとの各筒の確率（PolicyEntry::bestが最善手。残りが0個なら空）
+}
+
+// 途中まで積んだboardから残りn個（手元のカラーボールを含む）を積む場合の確率と、次に配られうる色ごとの最善手を求める
+// boardは文字列表記から読み込んだものでも良く、問題の色数に合わせて作り直してから計算する
+// 予告ありの問題には対応しない（PositionError::PreviewUnsupportedを返す）
+// キャッシュのキーは残りの個数を含まない（最初から積む場合は盤面から決まる）ため、呼び出しごとにキャッシュを作る
+pub fn analyze_position(n: usize, board: &Board, problem: &Problem) -> Result<Analysis, PositionError> {
+    if problem.preview() > 0 {
+        return Err(PositionError::PreviewUnsupported);
+    }
+    let board = rebuild(board, problem)?;
+    let capacity: usize = (0..board.width()).map(|x| board.height() - board.top(x)).sum();
+    if n > capacity {
+        return Err(PositionError::TooManyBalls { n, capacity });
+    }
+    if let Some(deck) = problem.deck() {
+        let remaining = deck.remaining(&board).ok_or(PositionError::DeckExhausted)?.total();
+        if remaining < n {
+            return Err(PositionError::DeckTooSmall { n, remaining });
+        }
+    }
+    let cache = &HashMapCache::with_len(0);
+    let entries = match n {
+        0 => vec![],
+        _ => problem.palette.all()
+            .filter(|&c| problem.color_probability(c, &board) > 0.0)
+            .map(|color| PolicyEntry { board, n, color, moves: evaluate_moves(n, board, color, problem, cache) })
+            .collect(),
+    };
+    Ok(Analysis { board, n, probability: probability(n, board, problem, cache), entries })
+}
+
+// 問題の色数のビット表現でBoardを作り直す（キャッシュのキーの長さを揃えるため）
+fn rebuild(board: &Board, problem: &Problem) -> Result<Board, PositionError> {
+    let mut rebuilt = Board::new(board.width(), board.height(), problem.palette.len())?;
+    for x in 0..board.width() {
+        for y in 0..board.top(x) {
+            let color = board.get(x, y).unwrap();
+            if color.0 >= problem.palette.len() {
+                return Err(PositionError::UnknownColor(color));
+            }
+            rebuilt.try_drop(x, color)?;
+        }
+    }
+    Ok(rebuilt)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probability_search::*;
+
+    #[test]
+    fn analyze() {
+        let problem = Problem::new(Palette::default(), 3);
+        let board: Board = "RR|G...".parse().unwrap();
+        let analysis = analyze_position(3, &board, &problem).unwrap();
+        let mut expected = problem.board(2, 4);
+        for (x, c) in [(0, Color::RED), (0, Color::RED), (1, Color::GREEN)] {
+            expected.drop(x, c);
+        }
+        assert_eq!(analysis.board, expected);
+        assert_eq!(analysis.probability, probability(3, expected, &problem, &HashMapCache::with_len(0)));
+        // 赤が来れば左の筒で揃う
+        assert_eq!(analysis.entries.len(), 3);
+        assert_eq!(analysis.entries[0].color, Color::RED);
+        assert_eq!(analysis.entries[0].best(), vec![0]);
+        let done = analyze_position(0, &board, &problem).unwrap();
+        assert_eq!((done.probability, done.entries.len()), (0.0, 0));
+        // 同じ盤面でも残りの個数が異なれば確率は異なる
+        let one = analyze_position(1, &board, &problem).unwrap();
+        assert_eq!(one.probability, probability(1, expected, &problem, &HashMapCache::with_len(0)));
+        assert_eq!(analyze_position(3, &board, &problem).unwrap().probability, analysis.probability);
+        assert_ne!(one.probability, analysis.probability);
+        // 整合性の検査
+        assert_eq!(analyze_position(6, &board, &problem).unwrap_err(),
+                   PositionError::TooManyBalls { n: 6, capacity: 5 });
+        assert_eq!(analyze_position(1, &"RY|..".parse().unwrap(), &problem).unwrap_err(),
+                   PositionError::UnknownColor(Color(3)));
+        let deck = problem.clone().with_deck(&[1, 2, 2]);
+        assert_eq!(analyze_position(1, &board, &deck).unwrap_err(), PositionError::DeckExhausted);
+        assert_eq!(analyze_position(4, &"R|G...".parse().unwrap(), &deck).unwrap_err(),
+                   PositionError::DeckTooSmall { n: 4, remaining: 3 });
+        let preview = problem.clone().with_preview(1);
+        assert_eq!(analyze_position(1, &board, &preview).unwrap_err(), PositionError::PreviewUnsupported);
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use super::{Board, Cache, Color, Dealt, Move, Probability, Problem};
+
+// 予告されるカラーボールの最大数（手元の1個と合わせて1個8ビットでu64に詰めるため）
+pub const MAX_PREVIEW: usize = 7;
+
+// 予告ありの問題で使える色数の上限（色のビット表現Color::to_bitが8ビットに収まるため）
+pub const MAX_PREVIEW_COLORS: usize = u8::MAX as usize;
+
+// 手元のカラーボールと、予告されている次以降のカラーボールの色の列（先頭が手元のカラーボール）
+// 色の番号を1個1バイトで持ち、長さは予告数の上限に手元の1個を加えたMAX_PREVIEW + 1個まで
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Queue {
+    colors: [u8; MAX_PREVIEW + 1],
+    len: u8,
+}
+
+impl Queue {
+    pub fn new(colors: &[Color]) -> Self {
+        colors.iter().fold(Self::default(), |queue, &color| queue.push(color))
+    }
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    // 手元のカラーボールの色
+    pub fn front(&self) -> Option<Color> {
+        self.iter().next()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = Color> + '_ {
+        self.colors[..self.len()].iter().map(|&c| Color(c as usize))
+    }
+    // 末尾にcolorを加えた列を返す
+    pub fn push(&self, color: Color) -> Self {
+        assert!(self.len() <= MAX_PREVIEW, "at most {} balls can be queued", MAX_PREVIEW + 1);
+        assert!(color.0 < MAX_PREVIEW_COLORS, "color {} does not fit in a queue", color.0);
+        let mut queue = *self;
+        queue.colors[self.len()] = color.0 as u8;
+        queue.len += 1;
+        queue
+    }
+    // 先頭（手元のカラーボール）を取り除いた列を返す
+    pub fn rest(&self) -> Self {
+        let mut queue = Self::default();
+        if !self.is_empty() {
+            queue.colors[..self.len() - 1].copy_from_slice(&self.colors[1..self.len()]);
+            queue.len = self.len - 1;
+        }
+        queue
+    }
+    // キャッシュのキーに使用するビット表現（色のビット表現を8ビットずつ詰める。空の列は0）
+    pub fn to_bits(&self) -> u64 {
+        self.iter().enumerate().fold(0, |bits, (i, color)| bits | (color.to_bit() as u64) << (i * 8))
+    }
+}
+
+// 予告ありの場合に、手元と予告のカラーボールがqueueの状態から残りn個（手元を含む）を積んで条件を満たす確率
+// queueの長さは予告数 + 1と残りの個数nのうち小さい方でなければならない
+pub fn probability_with_queue<P: Probability>(n: usize, board: Board, queue: &Queue, problem: &Problem,
+                                              cache: &impl Cache<P>) -> P {
+    if n == 0 {
+        return problem.terminal_value(&board);
+    }
+    assert_eq!(queue.len(), (problem.preview() + 1).min(n), "queue must hold the current and previewed balls");
+    let key = problem.key(&board, problem.remaining_deck(&board).as_ref(), queue);
+    if let Some(p) = cache.get(&key) {
+        return p;
+    }
+    // 予告されている色を見た上で、もっとも確率が高くなる筒に入れる
+    let p = moves_with_queue(n, board, queue, problem, cache).into_iter()
+        .map(|(_, p)| p)
+        .fold(P::zero(), |max, p| if p > max { p } else { max });
+    cache.set(&key, p.clone());
+    p
+}
+
+// 予告ありの場合に、手元のカラーボール（queueの先頭）を積める各筒に入れた場合の確率を返す
+pub fn evaluate_moves_with_queue(n: usize, board: Board, queue: &Queue, problem: &Problem,
+                                 cache: &impl Cache) -> Vec<Move> {
+    moves_with_queue(n, board, queue, problem, cache).into_iter()
+        .map(|(x, probability)| Move { x, probability })
+        .collect()
+}
+
+// evaluate_moves_with_queueの確率の型を問わない版。筒の番号と確率の組を返す
+fn moves_with_queue<P: Probability>(n: usize, board: Board, queue: &Queue, problem: &Problem,
+                                    cache: &impl Cache<P>) -> Vec<(usize, P)> {
+    let color = queue.front().expect("no ball is left to place");
+    let rest = queue.rest();
+    (0..board.width())
+        .filter(|&x| board.top(x) < board.height())
+        .map(|x| {
+            let mut board = board;
+            board.drop(x, color);
+            (x, fill_queue(n - 1, board, rest, problem, cache))
+        })
+        .collect()
+}
+
+// 予告の列が埋まるまで新たに予告されるカラーボールの色で場合分けし、確率の加重平均（配り手が敵対的なら最小値）を求める
+// 空の列から始めれば、まだ何も配られていない状態で条件を満たす確率になる
+pub(super) fn fill_queue<P: Probability>(n: usize, board: Board, queue: Queue, problem: &Problem,
+                                         cache: &impl Cache<P>) -> P {
+    if queue.len() >= (problem.preview() + 1).min(n) {
+        return probability_with_queue(n, board, &queue, problem, cache);
+    }
+    let deck = problem.remaining_deck_after(&board, &queue);
+    let mut dealt = Dealt::new(problem);
+    for color in problem.palette.all() {
+        let weight = problem.weight(color, deck.as_ref());
+        if weight > 0.0 {
+            dealt = dealt.add(weight, fill_queue(n, board, queue.push(color), problem, cache));
+        }
+    }
+    dealt.result(problem, deck.as_ref())
+}
+
+// fill_queueの並列版。threaded_n個目の予告が決まるまで色ごとにスレッドを立ち上げる
+pub(super) fn fill_queue_parallel<P, T>(n: usize, threaded_n: usize, board: Board, queue: Queue,
+                                        problem: &Problem, cache: &Arc<T>) -> P
+    where P: Probability, T: Cache<P> + Sync + Send + 'static
+{
+    if threaded_n == 0 || queue.len() >= (problem.preview() + 1).min(n) {
+        return fill_queue(n, board, queue, problem, cache.as_ref());
+    }
+    let deck = problem.remaining_deck_after(&board, &queue);
+    let handles: Vec<_> = problem.palette.all()
+        .map(|color| (problem.weight(color, deck.as_ref()), color))
+        .filter(|&(weight, _)| weight > 0.0)
+        .map(|(weight, color)| {
+            let problem = problem.clone();
+            let cache = Arc::clone(cache);
+            let handle = std::thread::spawn(move || {
+                fill_queue_parallel(n, threaded_n - 1, board, queue.push(color), &problem, &cache)
+            });
+            (weight, handle)
+        })
+        .collect();
+    handles.into_iter()
+        .fold(Dealt::new(problem), |dealt, (weight, handle)| dealt.add(weight, handle.join().unwrap()))
+        .result(problem, deck.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::probability_search::*;
+
+    #[test]
+    fn queue() {
+        let queue = Queue::new(&[Color::RED, Color::BLUE]);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.front(), Some(Color::RED));
+        assert_eq!(queue.rest(), Queue::new(&[Color::BLUE]));
+        assert_eq!(queue.push(Color::GREEN).iter().collect::<Vec<_>>(), vec![Color::RED, Color::BLUE, Color::GREEN]);
+        assert_ne!(queue.to_bits(), queue.rest().to_bits());
+        assert_eq!(Queue::default().to_bits(), 0);
+    }
+    #[test]
+    fn preview() {
+        let problem = Problem::new(Palette::default(), 3);
+        let p = probability(8, problem.board(2, 4), &problem, &HashMapCache::<f64>::with_len(0));
+        // 予告数0は予告なしと一致し、予告が多いほど確率は上がる
+        let mut last = 0.0;
+        for k in 0..=3 {
+            let preview = problem.clone().with_preview(k);
+            let cache = Arc::new(ShardedCache::<f64>::with_len(0));
+            let q = probability(8, preview.board(2, 4), &preview, cache.as_ref());
+            let r = probability_parallel(8, 2, preview.board(2, 4), &preview, &Arc::new(ShardedCache::<f64>::with_len(0)));
+            assert!((q - r).abs() < 1e-12);
+            // 密なキャッシュは予告を区別できないため、予告を含む状態を格納しない
+            let dense = RefCellCache::with_len(preview.cache_size(2, 4).unwrap());
+            assert!((q - probability(8, preview.board(2, 4), &preview, &dense)).abs() < 1e-12);
+            if k == 0 {
+                assert!((p - q).abs() < 1e-12);
+            } else {
+                assert!(q >= last, "{} < {}", q, last);
+            }
+            last = q;
+        }
+        // 全て予告されていれば、赤が2個積まれた筒に赤を入れる
+        let preview = problem.clone().with_preview(2);
+        let mut board = preview.board(2, 4);
+        board.drop(0, Color::RED);
+        board.drop(0, Color::RED);
+        let queue = Queue::new(&[Color::RED, Color::GREEN, Color::BLUE]);
+        let moves = evaluate_moves_with_queue(3, board, &queue, &preview, &HashMapCache::with_len(0));
+        assert_eq!(moves[0], Move { x: 0, probability: 1.0 });
+    }
+}
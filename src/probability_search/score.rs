@@ -0,0 +1,130 @@
+use std::fmt::Debug;
+
+use super::{Board, Direction, Run};
+
+// 積み終わったBoardの得点を求めるインターフェース
+// Problemに設定すると、条件を満たす確率の代わりに得点の期待値を最大化する
+// 得点は0以上でなければならない（どの筒にも入れられない場合の値を0として最大値を求めるため）
+pub trait Score: Debug + Send + Sync {
+    fn score(&self, board: &Board) -> f64;
+    // 筒を並べ替えても得点が変わらない場合はtrueを返す（メモ化のキーで筒の並びを区別しなくてよい）
+    fn is_tube_symmetric(&self) -> bool { true }
+    // 色を付け替えても得点が変わらない場合はtrueを返す（Problem::symmetryを使用できる）
+    fn is_color_symmetric(&self) -> bool { true }
+}
+
+// directionの向きに並ぶ、これ以上伸ばせない同色の並びごとに、長さに応じた点数を与える
+// points[i]が長さi + 1の並びの点数で、points.len()より長い並びには最後の点数を与える
+// color_factorsを指定した場合は、並びの色ごとに点数を掛ける
+#[derive(Clone, PartialEq, Debug)]
+pub struct RunScore {
+    pub direction: Direction,
+    pub points: Vec<f64>,
+    pub color_factors: Option<Vec<f64>>,
+}
+
+impl RunScore {
+    pub fn new(direction: Direction, points: &[f64]) -> Self {
+        assert!(points.iter().all(|p| p.is_finite() && *p >= 0.0), "points must be non-negative");
+        Self { direction, points: points.to_vec(), color_factors: None }
+    }
+    // 色ごとに点数を掛ける係数を設定する（色番号順）
+    pub fn with_color_factors(self, factors: &[f64]) -> Self {
+        assert!(factors.iter().all(|f| f.is_finite() && *f >= 0.0), "color factors must be non-negative");
+        Self { color_factors: Some(factors.to_vec()), ..self }
+    }
+}
+
+impl Score for RunScore {
+    fn score(&self, board: &Board) -> f64 {
+        let Some(last) = self.points.last() else {
+            return 0.0;
+        };
+        self.direction.steps().iter()
+            .flat_map(|&step| Run::runs(board, step))
+            .map(|(color, len)| {
+                let points = self.points.get(len - 1).unwrap_or(last);
+                let factor = self.color_factors.as_ref().map_or(1.0, |f| f.get(color.0).copied().unwrap_or(1.0));
+                points * factor
+            })
+            .sum()
+    }
+    fn is_tube_symmetric(&self) -> bool {
+        self.direction == Direction::Vertical
+    }
+    fn is_color_symmetric(&self) -> bool {
+        self.color_factors.as_ref().is_none_or(|f| f.iter().all(|&x| x == f[0]))
+    }
+}
+
+// 全ての得点の合計
+#[derive(Debug)]
+pub struct Sum(pub Vec<Box<dyn Score>>);
+
+impl Score for Sum {
+    fn score(&self, board: &Board) -> f64 {
+        self.0.iter().map(|s| s.score(board)).sum()
+    }
+    fn is_tube_symmetric(&self) -> bool {
+        self.0.iter().all(|s| s.is_tube_symmetric())
+    }
+    fn is_color_symmetric(&self) -> bool {
+        self.0.iter().all(|s| s.is_color_symmetric())
+    }
+}
+
+// 得点の式の文字列を解析する
+// "vertical:0,0,1,3"のように向きと長さ1からの点数を並べた項を'+'で区切って合計する
+// color_factorsを指定した場合は全ての項に色ごとの係数を設定する
+pub fn parse_score(s: &str, color_factors: Option<&[f64]>) -> Result<Box<dyn Score>, String> {
+    let mut scores = s.split('+')
+        .map(|term| {
+            let term = term.trim();
+            let (direction, points) = term.split_once(':').ok_or(format!("missing points in {}", term))?;
+            let direction = match direction {
+                "vertical" => Direction::Vertical,
+                "horizontal" => Direction::Horizontal,
+                "diagonal" => Direction::Diagonal,
+                _ => return Err(format!("unknown direction in {}", term)),
+            };
+            let points = points.split(',')
+                .map(|p| match p.trim().parse::<f64>() {
+                    Ok(p) if p.is_finite() && p >= 0.0 => Ok(p),
+                    _ => Err(format!("invalid points in {}: {}", term, p)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let score = RunScore::new(direction, &points);
+            Ok(Box::new(match color_factors {
+                Some(factors) => score.with_color_factors(factors),
+                None => score,
+            }) as Box<dyn Score>)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(if scores.len() == 1 { scores.pop().unwrap() } else { Box::new(Sum(scores)) })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probability_search::*;
+
+    #[test]
+    fn run_scores() {
+        let mut board = Board::with_size(2, 4, 3);
+        for (x, c) in [(0, Color::RED), (0, Color::RED), (0, Color::BLUE), (1, Color::RED)] {
+            board.drop(x, c);
+        }
+        // 縦は赤2個、青1個、赤1個、横は赤2個、赤1個、青1個の並び
+        let vertical = RunScore::new(Direction::Vertical, &[0.0, 1.0, 5.0]);
+        assert_eq!(vertical.score(&board), 1.0);
+        let horizontal = RunScore::new(Direction::Horizontal, &[1.0, 3.0]);
+        assert_eq!(horizontal.score(&board), 5.0);
+        let weighted = vertical.clone().with_color_factors(&[2.0, 1.0, 1.0]);
+        assert_eq!(weighted.score(&board), 2.0);
+        assert!(!weighted.is_color_symmetric() && vertical.is_tube_symmetric() && !horizontal.is_tube_symmetric());
+        let sum = parse_score("vertical:0,1,5 + horizontal:1,3", None).unwrap();
+        assert_eq!(sum.score(&board), 6.0);
+        assert!(parse_score("vertical", None).is_err());
+        assert!(parse_score("up:1", None).is_err());
+        assert!(parse_score("vertical:-1", None).is_err());
+    }
+}
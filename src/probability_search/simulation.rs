@@ -0,0 +1,179 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::{best_moves, evaluate_moves_with_queue, Board, Cache, Color, Problem, Queue};
+use crate::random::XorShift;
+
+// シミュレーションでカラーボールを入れる筒の選び方
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    Optimal, // probabilityが最大となる筒（最善手が複数ある場合は番号の小さい筒。予告ありの場合は予告を考慮する）
+    Greedy, // 筒の一番上から続く同色の連結が最も長くなる筒（同じ長さなら空きの多い筒）
+    Random, // 積める筒から等確率で選ぶ
+}
+
+impl Strategy {
+    pub fn all() -> Vec<Self> {
+        vec![Self::Optimal, Self::Greedy, Self::Random]
+    }
+    // 残りn個の時点で手元のカラーボール（queueの先頭。予告ありの場合は予告の列が続く）を入れる筒を選ぶ
+    fn choose(&self, n: usize, board: Board, queue: &Queue, problem: &Problem, cache: &impl Cache,
+              rng: &mut XorShift) -> usize {
+        let color = queue.front().unwrap();
+        let open: Vec<usize> = (0..board.width()).filter(|&x| board.top(x) < board.height()).collect();
+        match self {
+            Self::Optimal if problem.preview() > 0 => {
+                let moves = evaluate_moves_with_queue(n, board, queue, problem, cache);
+                let max = moves.iter().map(|m| m.probability).fold(0.0, f64::max);
+                moves.iter().find(|m| m.probability == max).unwrap().x
+            }
+            Self::Optimal => best_moves(n, board, color, problem, cache)[0].x,
+            Self::Greedy => *open.iter()
+                .max_by_key(|&&x| (top_run(&board, x, color), board.height() - board.top(x), usize::MAX - x))
+                .unwrap(),
+            Self::Random => open[rng.gen_range(open.len())],
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Optimal => "optimal",
+            Self::Greedy => "greedy",
+            Self::Random => "random",
+        };
+        f.pad(name)
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::all().into_iter()
+            .find(|strategy| strategy.to_string() == s)
+            .ok_or_else(|| format!("unknown strategy: {}", s))
+    }
+}
+
+// x番目の筒にcolorを入れた場合に、一番上から続く同色のカラーボールの個数
+fn top_run(board: &Board, x: usize, color: Color) -> usize {
+    1 + (0..board.top(x)).rev().take_while(|&y| board.get(x, y) == Some(color)).count()
+}
+
+// シミュレーションの結果（試行回数と条件を満たした回数）
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Estimate {
+    pub games: u64,
+    pub wins: u64,
+}
+
+impl Estimate {
+    // 条件を満たした割合
+    pub fn rate(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            self.wins as f64 / self.games as f64
+        }
+    }
+    // Wilsonのスコア区間による信頼区間（zは標準正規分布の分位点。95%なら1.96）
+    // 成功率が0や1に近い場合でも区間が[0, 1]に収まる
+    pub fn confidence_interval(&self, z: f64) -> (f64, f64) {
+        if self.games == 0 {
+            return (0.0, 1.0);
+        }
+        let n = self.games as f64;
+        let p = self.rate();
+        let denominator = 1.0 + z * z / n;
+        let center = (p + z * z / (2.0 * n)) / denominator;
+        let margin = z * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt() / denominator;
+        ((center - margin).max(0.0), (center + margin).min(1.0))
+    }
+    fn merge(self, other: Self) -> Self {
+        Self { games: self.games + other.games, wins: self.wins + other.wins }
+    }
+}
+
+// boardから残りn個をstrategyに従って積むゲームを1回行い、条件を満たしたらtrueを返す
+fn play_game(n: usize, mut board: Board, problem: &Problem, strategy: Strategy, cache: &impl Cache,
+             rng: &mut XorShift) -> bool {
+    let mut queue = Queue::default();
+    for rest in (1..=n).rev() {
+        // 手元のカラーボールと予告のカラーボールが揃うまで配る
+        while queue.len() < (problem.preview() + 1).min(rest) {
+            queue = queue.push(problem.sample_color(&board, &queue, rng));
+        }
+        let x = strategy.choose(rest, board, &queue, problem, cache, rng);
+        board.drop(x, queue.front().unwrap());
+        queue = queue.rest();
+    }
+    problem.is_satisfied(&board)
+}
+
+// ランダムに配られるカラーボールをstrategyに従って積むゲームをgames回行い、条件を満たした回数を数える
+// Optimalの場合はcacheに最善手の計算結果がメモ化される
+pub fn simulate(n: usize, board: Board, problem: &Problem, strategy: Strategy, games: u64, seed: u64,
+                cache: &impl Cache) -> Estimate {
+    let mut rng = XorShift::new(seed);
+    let wins = (0..games).filter(|_| play_game(n, board, problem, strategy, cache, &mut rng)).count();
+    Estimate { games, wins: wins as u64 }
+}
+
+// games回のゲームをthread_num個のスレッドに分割して並列にシミュレーションする
+// i番目のスレッドはseed + iをシード値とするため、thread_numが同じであれば結果は再現する
+#[allow(clippy::too_many_arguments)] // simulateの引数にスレッド数を加えたもの
+pub fn simulate_parallel<T>(n: usize, board: Board, problem: &Problem, strategy: Strategy, games: u64,
+                            seed: u64, thread_num: usize, cache: &Arc<T>) -> Estimate
+    where T: Cache + Sync + Send + 'static
+{
+    let thread_num = thread_num.max(1) as u64;
+    let handles: Vec<_> = (0..thread_num)
+        .map(|i| {
+            // 端数は先頭のスレッドから1回ずつ割り振る
+            let games = games / thread_num + u64::from(i < games % thread_num);
+            let problem = problem.clone();
+            let cache = Arc::clone(cache);
+            std::thread::spawn(move || {
+                simulate(n, board, &problem, strategy, games, seed.wrapping_add(i), cache.as_ref())
+            })
+        })
+        .collect();
+    handles.into_iter().map(|handle| handle.join().unwrap()).fold(Estimate::default(), Estimate::merge)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::probability_search::*;
+
+    #[test]
+    fn confidence_interval() {
+        let estimate = Estimate { games: 100, wins: 50 };
+        let (low, high) = estimate.confidence_interval(1.96);
+        assert!((low - 0.4038).abs() < 1e-4 && (high - 0.5962).abs() < 1e-4, "{} {}", low, high);
+        assert_eq!(Estimate { games: 10, wins: 10 }.confidence_interval(1.96).1, 1.0);
+        assert_eq!(Estimate::default().confidence_interval(1.96), (0.0, 1.0));
+    }
+    #[test]
+    fn optimal_strategy_matches_probability() {
+        let problem = Problem::new(Palette::default(), 3);
+        let board = problem.board(2, 4);
+        let cache = Arc::new(ShardedCache::with_len(0));
+        let p = probability(8, board, &problem, cache.as_ref());
+        let estimate = simulate_parallel(8, board, &problem, Strategy::Optimal, 20_000, 1, 4, &cache);
+        assert_eq!(estimate.games, 20_000);
+        // 99.9%信頼区間に正確な確率が含まれる
+        let (low, high) = estimate.confidence_interval(3.29);
+        assert!(low <= p && p <= high, "{} not in [{}, {}]", p, low, high);
+        // 最善手以外の戦略は最善手を上回らない
+        for strategy in [Strategy::Greedy, Strategy::Random] {
+            let estimate = simulate(8, board, &problem, strategy, 20_000, 1, &NoCache::with_len(0));
+            assert!(estimate.confidence_interval(3.29).0 <= p);
+        }
+        assert_eq!(simulate(8, board, &problem, Strategy::Random, 100, 5, cache.as_ref()),
+                   simulate(8, board, &problem, Strategy::Random, 100, 5, cache.as_ref()));
+    }
+}
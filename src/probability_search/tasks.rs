@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc};
+
+use threadpool::ThreadPool;
+
+use super::{probability, Board, Cache, HashMapCache, Key, Probability, Problem, Queue};
+
+// 1ワーカーあたりに割り当てるタスク数の目安（タスクごとの計算量のばらつきを均すため、ワーカー数より十分多くする）
+const TASKS_PER_WORKER: usize = 16;
+
+// thread_num個のワーカーを持つスレッドプールで並列計算を実施
+// probability_parallelのように分岐ごとにスレッドを立ち上げる代わりに、到達しうる状態の数がワーカー数の
+// TASKS_PER_WORKER倍以上になる深さを自動で選び、その深さの状態をそれぞれタスクとしてスレッドプールで計算する
+// 深さより手前の状態は、タスクの結果を使って呼び出し元のスレッドで計算する
+// 予告ありの問題には対応しない
+pub fn probability_pooled<P, T>(n: usize, board: Board, problem: &Problem, cache: &Arc<T>, thread_num: usize) -> P
+    where P: Probability, T: Cache<P> + Sync + Send + 'static
+{
+    assert_eq!(problem.preview(), 0, "the pooled solver does not support a preview");
+    assert!(thread_num > 0, "at least one thread is required");
+    let (depth, frontier) = split(n, board, problem, thread_num * TASKS_PER_WORKER);
+    if depth == 0 {
+        return probability(n, board, problem, cache.as_ref());
+    }
+    let pool = ThreadPool::new(thread_num);
+    let (tx, rx) = mpsc::channel();
+    let task_num = frontier.len();
+    for (key, board) in frontier {
+        let tx = tx.clone();
+        let problem = problem.clone();
+        let cache = Arc::clone(cache);
+        pool.execute(move || {
+            let p = probability(n - depth, board, &problem, cache.as_ref());
+            tx.send((key, p)).expect("channel will be there waiting for the pool");
+        });
+    }
+    // 送信側を全てのタスクが手放せば受信が終わるため、パニックしたタスクがあっても待ち続けない
+    drop(tx);
+    // タスクの結果を格納したキャッシュを使って、深さより手前の状態の確率を求める
+    let top = HashMapCache::with_len(task_num);
+    let mut received = 0;
+    for (key, p) in rx.iter() {
+        top.set(&key, p);
+        received += 1;
+    }
+    assert_eq!(received, task_num, "{} tasks panicked in the thread pool", task_num - received);
+    probability(n, board, problem, &top)
+}
+
+// 到達しうる状態の数がtask_num以上になる深さ（残りが1個になる深さまで）と、その深さの状態を重複なく列挙する
+fn split(n: usize, board: Board, problem: &Problem, task_num: usize) -> (usize, Vec<(Key, Board)>) {
+    let mut frontier = vec![(key(problem, &board), board)];
+    let mut depth = 0;
+    while frontier.len() < task_num && depth + 1 < n {
+        let mut next = HashMap::new();
+        for (_, board) in &frontier {
+            let deck = problem.remaining_deck(board);
+            for color in problem.palette.all().filter(|&c| problem.weight(c, deck.as_ref()) > 0.0) {
+                for x in (0..board.width()).filter(|&x| board.top(x) < board.height()) {
+                    let mut board = *board;
+                    board.drop(x, color);
+                    next.entry(key(problem, &board)).or_insert(board);
+                }
+            }
+        }
+        frontier = next.into_iter().collect();
+        depth += 1;
+    }
+    (depth, frontier)
+}
+
+// 筒の並びだけが異なる状態を同じタスクにまとめるためのキー
+fn key(problem: &Problem, board: &Board) -> Key {
+    problem.key(board, problem.remaining_deck(board).as_ref(), &Queue::default()).canonical()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::probability_search::*;
+
+    #[test]
+    fn pooled() {
+        let problem = Problem::new(Palette::default(), 3);
+        let p: f64 = probability(8, problem.board(3, 4), &problem, &HashMapCache::with_len(0));
+        for thread_num in [1, 2, 4] {
+            let q: f64 = probability_pooled(8, problem.board(3, 4), &problem, &Arc::new(ShardedCache::with_len(0)),
+                                            thread_num);
+            assert!((p - q).abs() < 1e-12, "{} != {}", p, q);
+        }
+        // 分割する深さに達しない小さな問題や、キャッシュ無しでも求められる
+        let q: f64 = probability_pooled(1, problem.board(3, 4), &problem, &Arc::new(NoCache::with_len(0)), 4);
+        assert_eq!(q, 0.0);
+        let deck = problem.clone().with_deck(&[3, 2, 2]);
+        let p: f64 = probability(6, deck.board(2, 3), &deck, &NoCache::with_len(0));
+        let q: f64 = probability_pooled(6, deck.board(2, 3), &deck, &Arc::new(NoCache::with_len(0)), 2);
+        assert!((p - q).abs() < 1e-12);
+    }
+}
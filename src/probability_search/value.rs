@@ -0,0 +1,134 @@
+use std::fmt::Debug;
+use std::ops::{Add, Div, Mul};
+
+use num::{BigRational, FromPrimitive, One, ToPrimitive, Zero};
+
+// 確率（得点を設定した場合は得点の期待値）の計算に使用する数値型
+// f64は高速だが丸め誤差を含む。BigRationalは遅いが厳密な分数で求められるため、f64の結果の検証に使用できる
+// Momentsは得点の期待値と2乗の期待値を同時に求め、分散の計算に使用する
+pub trait Probability: Zero + One + Add<Output = Self> + Mul<Output = Self> + Div<Output = Self>
+    + Clone + PartialOrd + Debug + Send + Sync + 'static
+{
+    // 色の重みや袋に残っている個数をこの型で表す（f64の値は誤差なく変換される）
+    fn from_weight(weight: f64) -> Self;
+    // 積み終わったBoardの得点をこの型で表す
+    fn from_score(score: f64) -> Self {
+        Self::from_weight(score)
+    }
+    fn to_f64(&self) -> f64;
+}
+
+impl Probability for f64 {
+    fn from_weight(weight: f64) -> Self {
+        weight
+    }
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+}
+
+impl Probability for BigRational {
+    fn from_weight(weight: f64) -> Self {
+        Self::from_f64(weight).expect("weight must be finite")
+    }
+    fn to_f64(&self) -> f64 {
+        ToPrimitive::to_f64(self).unwrap_or(f64::NAN)
+    }
+}
+
+// 得点の期待値meanと2乗の期待値square
+// 演算は成分ごとに行い、重みは両方の成分に同じ値を持たせることで、加重平均が両方の成分で同時に求まる
+// 大小は期待値で比較する（期待値が等しい場合は2乗の期待値の大きい方を選ぶ）
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default)]
+pub struct Moments {
+    pub mean: f64,
+    pub square: f64,
+}
+
+impl Moments {
+    pub fn variance(&self) -> f64 {
+        (self.square - self.mean * self.mean).max(0.0)
+    }
+}
+
+impl Add for Moments {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self { mean: self.mean + rhs.mean, square: self.square + rhs.square }
+    }
+}
+
+impl Mul for Moments {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self { mean: self.mean * rhs.mean, square: self.square * rhs.square }
+    }
+}
+
+impl Div for Moments {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        Self { mean: self.mean / rhs.mean, square: self.square / rhs.square }
+    }
+}
+
+impl Zero for Moments {
+    fn zero() -> Self {
+        Self::default()
+    }
+    fn is_zero(&self) -> bool {
+        self.mean == 0.0 && self.square == 0.0
+    }
+}
+
+impl One for Moments {
+    fn one() -> Self {
+        Self { mean: 1.0, square: 1.0 }
+    }
+}
+
+impl Probability for Moments {
+    fn from_weight(weight: f64) -> Self {
+        Self { mean: weight, square: weight }
+    }
+    fn from_score(score: f64) -> Self {
+        Self { mean: score, square: score * score }
+    }
+    fn to_f64(&self) -> f64 {
+        self.mean
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use num::BigRational;
+
+    use crate::probability_search::*;
+
+    fn ratio(numer: i64, denom: i64) -> BigRational {
+        BigRational::new(numer.into(), denom.into())
+    }
+
+    #[test]
+    fn exact_probability() {
+        let problem = Problem::new(Palette::default(), 3);
+        let exact: BigRational = probability(6, problem.board(2, 3), &problem, &HashMapCache::with_len(0));
+        assert_eq!(exact, ratio(167, 243));
+        let parallel: BigRational = probability_parallel(6, 2, problem.board(2, 3), &problem,
+                                                         &Arc::new(ShardedCache::with_len(0)));
+        assert_eq!(parallel, exact);
+        let p: f64 = probability(6, problem.board(2, 3), &problem, &HashMapCache::with_len(0));
+        assert!((p - exact.to_f64()).abs() < 1e-12);
+        // 袋から配る場合や予告ありの場合も分数で求められる
+        let deck = Problem::new(Palette::default(), 2).with_deck(&[2, 1, 1]);
+        let exact: BigRational = probability(3, deck.board(2, 3), &deck, &HashMapCache::with_len(0));
+        let p: f64 = probability(3, deck.board(2, 3), &deck, &HashMapCache::with_len(0));
+        assert!((p - exact.to_f64()).abs() < 1e-12);
+        let preview = problem.clone().with_preview(2);
+        let exact: BigRational = probability(6, preview.board(2, 3), &preview, &HashMapCache::with_len(0));
+        let p: f64 = probability(6, preview.board(2, 3), &preview, &HashMapCache::with_len(0));
+        assert!(exact > ratio(167, 243) && (p - exact.to_f64()).abs() < 1e-12);
+    }
+}
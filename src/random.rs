@@ -0,0 +1,73 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// シード値を指定して再現可能な乱数列を生成する軽量な疑似乱数生成器（xorshift64*）
+// 暗号用途には使用できない
+#[derive(Clone, Debug)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // 状態が0になると以降ずっと0を返すため、シード値をsplitmix64で撹拌してから使う
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Self { state: if z == 0 { 1 } else { z } }
+    }
+    // 現在時刻をシード値とする
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+        Self::new(nanos as u64)
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+    // [0, 1)の一様乱数
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    // [0, n)の一様な整数乱数
+    pub fn gen_range(&mut self, n: usize) -> usize {
+        assert!(n > 0, "range must not be empty");
+        // 剰余による偏りを避けるため、nの倍数に収まらない値は棄却する
+        let n = n as u64;
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let r = self.next_u64();
+            if r < zone {
+                return (r % n) as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::random::XorShift;
+
+    #[test]
+    fn reproducible() {
+        let mut a = XorShift::new(42);
+        let mut b = XorShift::new(42);
+        let mut c = XorShift::new(43);
+        let va: Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+        assert_eq!(va, (0..10).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(va, (0..10).map(|_| c.next_u64()).collect::<Vec<_>>());
+        assert_ne!(XorShift::new(0).next_u64(), 0);
+    }
+    #[test]
+    fn uniform_range() {
+        let mut rng = XorShift::new(1);
+        let mut counts = [0; 3];
+        for _ in 0..30_000 {
+            counts[rng.gen_range(3)] += 1;
+        }
+        assert!(counts.iter().all(|&c| (9_000..11_000).contains(&c)), "{:?}", counts);
+        assert!((0..1000).map(|_| rng.next_f64()).all(|x| (0.0..1.0).contains(&x)));
+    }
+}